@@ -0,0 +1,18 @@
+#!/bin/sh
+# Builds every benchmark in bench/ at -O0 and -O2 and times a run of each.
+# Run from the repository root, optionally with the compiler to use:
+#   bench/run.sh [path/to/something]
+set -e
+something=${1:-target/release/something}
+out=$(mktemp -d)
+trap 'rm -rf "$out"' EXIT
+for file in bench/*.som; do
+  name=$(basename "$file" .som)
+  for level in 0 2; do
+    "$something" build -O"$level" "$file" -o "$out/$name"
+    start=$(date +%s%N)
+    "$out/$name" > /dev/null
+    end=$(date +%s%N)
+    echo "$name -O$level: $(( (end - start) / 1000000 )) ms"
+  done
+done
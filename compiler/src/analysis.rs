@@ -0,0 +1,367 @@
+use crate::diagnostic::{self, Diagnostic};
+use crate::parse::{self, ParseTok, ParseType};
+use crate::pretty::prim_name;
+use crate::{loc_within, string_parts, LexToken, Lexer, StringPart, TokenType};
+use std::collections::HashMap;
+
+pub use crate::som_std::{std_detail, STD_FNS};
+
+// What editors need to know about a source file: the names it defines, where
+// they can be used and what is wrong with it. Offsets count characters from the
+// start of the file, the same as parser locations.
+
+pub const KEYWORDS: [&str; 5] = ["ret", "end", "test", "not", "as"];
+pub const TYPES: [&str; 7] = ["i8", "i16", "i32", "i64", "f32", "f64", "str"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Parameter,
+    Test,
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // Signature shown on hover, `add(x i32, y i32): i32` or `x: i32`.
+    pub detail: String,
+    // The name at the definition.
+    pub start: usize,
+    pub end: usize,
+    // The whole definition, up to `end` for functions and tests.
+    pub range: (usize, usize),
+    // Where the symbol can be referred to.
+    pub scope: (usize, usize),
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub diagnostics: Vec<Diagnostic>,
+    // Every label in the file as (start, end, name).
+    words: Vec<(usize, usize, String)>,
+    // False when lexing or parsing stopped at an error.
+    pub complete: bool,
+}
+
+pub fn analyse(source: &str) -> Analysis {
+    let mut analysis = Analysis::default();
+    let len = source.chars().count();
+    if source.trim().is_empty() {
+        analysis.complete = true;
+        return analysis;
+    }
+
+    let (tokens, mut diagnostics) = diagnostic::catch(|| {
+        let mut lexer = Lexer::new(source.chars().collect());
+        lexer.lex();
+        lexer.tree()
+    });
+    analysis.diagnostics.append(&mut diagnostics);
+    let tokens = match tokens {
+        Some(tokens) if !tokens.is_empty() => tokens,
+        Some(_) => {
+            analysis.complete = true;
+            return analysis;
+        }
+        None => return analysis,
+    };
+    analysis.words = tokens
+        .iter()
+        .flat_map(|tok| match tok.tok_type() {
+            TokenType::STRING => interpolated(tok),
+            _ => vec![tok.clone()],
+        })
+        .filter(|tok| tok.tok_type() == &TokenType::LABEL)
+        .map(|tok| {
+            let end = tok.start() + tok.content().chars().count();
+            (tok.start(), end, tok.content().to_string())
+        })
+        .collect();
+
+    let (tree, mut diagnostics) = diagnostic::catch(|| {
+        let mut parser = parse::Parser::new(tokens.clone(), source.to_string(), HashMap::new());
+        parser.init();
+        parser.tree()
+    });
+    analysis.diagnostics.append(&mut diagnostics);
+    if let Some(tree) = tree {
+        for tok in &tree {
+            collect(tok, &tokens, (0, len), &mut analysis.symbols);
+        }
+        analysis.complete = true;
+    }
+    analysis
+}
+
+// Tokens of the expressions interpolated into a string, so names inside
+// strings can be looked up too. Errors in them are reported by the parser.
+fn interpolated(tok: &LexToken) -> Vec<LexToken> {
+    let mut tokens = vec![];
+    for part in string_parts(tok.content()) {
+        if let StringPart::Code(index, code) = part {
+            let base = loc_within(tok.loc(), tok.content(), index);
+            let (lexed, _) = diagnostic::catch(|| {
+                let mut lexer = Lexer::embedded(code.chars().collect(), base);
+                lexer.lex();
+                lexer.tree()
+            });
+            tokens.append(&mut lexed.unwrap_or_default());
+        }
+    }
+    tokens
+}
+
+fn collect(tok: &ParseTok, tokens: &[LexToken], within: (usize, usize), out: &mut Vec<Symbol>) {
+    let loc = &tok.location;
+    match tok.tok_type {
+        ParseType::FNMAKE => {
+            let func = tok.fnmake.as_ref().as_ref().unwrap();
+            let range = (loc.start_col, loc.end_col);
+            let params: Vec<String> = func
+                .params
+                .iter()
+                .map(|p| format!("{} {}", p.name, prim_name(&p.value_type)))
+                .collect();
+            let mut children = vec![];
+            for param in &func.params {
+                if let Some(tok) = param_token(tokens, loc.start_col, &param.name) {
+                    let start = tok.start();
+                    children.push(Symbol {
+                        name: param.name.clone(),
+                        kind: SymbolKind::Parameter,
+                        detail: format!("{}: {}", param.name, prim_name(&param.value_type)),
+                        start,
+                        end: start + param.name.chars().count(),
+                        range: (start, start + param.name.chars().count()),
+                        scope: range,
+                        children: vec![],
+                    });
+                }
+            }
+            for statement in &func.body {
+                collect(statement, tokens, range, &mut children);
+            }
+            out.push(Symbol {
+                name: func.name.clone(),
+                kind: SymbolKind::Function,
+                detail: format!(
+                    "{}({}): {}",
+                    func.name,
+                    params.join(", "),
+                    prim_name(&func.return_type)
+                ),
+                start: loc.start_col,
+                end: loc.start_col + func.name.chars().count(),
+                range,
+                scope: (loc.start_col, within.1),
+                children,
+            });
+        }
+        ParseType::VARDEF => {
+            let var = tok.variable.as_ref().as_ref().unwrap();
+            out.push(Symbol {
+                name: var.name.clone(),
+                kind: SymbolKind::Variable,
+                detail: format!("{}: {}", var.name, prim_name(&var.value_type)),
+                start: loc.start_col,
+                end: loc.start_col + var.name.chars().count(),
+                range: (loc.start_col, loc.end_col),
+                scope: (loc.start_col, within.1),
+                children: vec![],
+            });
+        }
+        ParseType::TEST => {
+            let test = tok.test.as_ref().as_ref().unwrap();
+            let range = (loc.start_col, loc.end_col);
+            let mut children = vec![];
+            for statement in &test.body {
+                collect(statement, tokens, range, &mut children);
+            }
+            out.push(Symbol {
+                name: test.name.clone(),
+                kind: SymbolKind::Test,
+                detail: format!("test {:?}", test.name),
+                start: loc.start_col,
+                end: loc.start_col + "test".len(),
+                range,
+                scope: (loc.start_col, loc.start_col),
+                children,
+            });
+        }
+        _ => {}
+    }
+}
+
+// Parameters only keep their names, so find them in the tokens between the
+// function name and the `:` before its return type.
+fn param_token<'a>(tokens: &'a [LexToken], fn_start: usize, name: &str) -> Option<&'a LexToken> {
+    tokens
+        .iter()
+        .skip_while(|tok| tok.start() <= fn_start)
+        .take_while(|tok| tok.tok_type() != &TokenType::COLON)
+        .find(|tok| tok.content() == name)
+}
+
+fn flatten<'a>(symbols: &'a [Symbol], out: &mut Vec<&'a Symbol>) {
+    for symbol in symbols {
+        out.push(symbol);
+        flatten(&symbol.children, out);
+    }
+}
+
+impl Analysis {
+    // The label under `offset`, as (start, end, name).
+    pub fn word_at(&self, offset: usize) -> Option<(usize, usize, &str)> {
+        self.words
+            .iter()
+            .find(|(start, end, _)| *start <= offset && offset <= *end)
+            .map(|(start, end, name)| (*start, *end, name.as_str()))
+    }
+
+    // Symbols that can be referred to at `offset`, innermost definitions first.
+    pub fn in_scope(&self, offset: usize) -> Vec<&Symbol> {
+        let mut all = vec![];
+        flatten(&self.symbols, &mut all);
+        let mut visible: Vec<&Symbol> = all
+            .into_iter()
+            .filter(|s| s.kind != SymbolKind::Test && s.scope.0 <= offset && offset <= s.scope.1)
+            .collect();
+        visible.sort_by_key(|s| std::cmp::Reverse(s.scope.0));
+        let mut seen = vec![];
+        visible.retain(|s| {
+            let fresh = !seen.contains(&s.name);
+            seen.push(s.name.clone());
+            fresh
+        });
+        visible
+    }
+
+    // The definition of the name under `offset`.
+    pub fn definition(&self, offset: usize) -> Option<&Symbol> {
+        let (start, _, name) = self.word_at(offset)?;
+        self.in_scope(start).into_iter().find(|s| s.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "add x i32, y i32: i32
+    sum: i32: (x + y)
+    ret sum;
+end
+total: i32: !(add 1, 2)
+";
+
+    fn at(needle: &str) -> usize {
+        SOURCE.find(needle).unwrap()
+    }
+
+    #[test]
+    fn collects_symbols() {
+        let analysis = analyse(SOURCE);
+        assert!(analysis.complete);
+        assert!(analysis.diagnostics.is_empty());
+        let names: Vec<&str> = analysis.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["add", "total"]);
+        let add = &analysis.symbols[0];
+        assert_eq!(add.kind, SymbolKind::Function);
+        assert_eq!(add.detail, "add(x i32, y i32): i32");
+        let children: Vec<(&str, SymbolKind)> = add
+            .children
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind.clone()))
+            .collect();
+        assert_eq!(
+            children,
+            [
+                ("x", SymbolKind::Parameter),
+                ("y", SymbolKind::Parameter),
+                ("sum", SymbolKind::Variable)
+            ]
+        );
+        assert_eq!(analysis.symbols[1].detail, "total: i32");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let analysis = analyse("x: i32: 1\ny: i32: \"text\"\n");
+        assert!(!analysis.complete);
+        assert!(analysis.symbols.is_empty());
+        let error = &analysis.diagnostics[0];
+        assert_eq!(error.severity, diagnostic::Severity::Error);
+        assert_eq!(error.line, 2);
+
+        let analysis = analyse("add x i32: i32\n    ret x;\n");
+        assert!(!analysis.complete);
+        assert!(analysis.diagnostics[0].message.contains("missing end"));
+    }
+
+    #[test]
+    fn half_typed_source() {
+        for source in ["x: i32: 1\n-\n", "x: i32: 1\n~x\n", "x: i32: 1\nnot x\n"] {
+            let analysis = analyse(source);
+            assert!(!analysis.complete);
+            assert_eq!(analysis.diagnostics[0].line, 2);
+        }
+    }
+
+    #[test]
+    fn empty_source() {
+        let analysis = analyse("  \n");
+        assert!(analysis.complete);
+        assert!(analysis.symbols.is_empty());
+        assert!(analysis.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn finds_words() {
+        let analysis = analyse(SOURCE);
+        let total = at("total");
+        assert_eq!(analysis.word_at(total), Some((total, total + 5, "total")));
+        assert_eq!(
+            analysis.word_at(total + 5),
+            Some((total, total + 5, "total"))
+        );
+        assert_eq!(analysis.word_at(at(": i32\n") + 1), None);
+    }
+
+    #[test]
+    fn scopes() {
+        let analysis = analyse(SOURCE);
+        let names = |offset: usize| -> Vec<String> {
+            let mut names: Vec<String> = analysis
+                .in_scope(offset)
+                .iter()
+                .map(|s| s.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(at("ret")), ["add", "sum", "x", "y"]);
+        assert_eq!(names(at("!(add")), ["add", "total"]);
+    }
+
+    #[test]
+    fn goes_to_definitions() {
+        let analysis = analyse(SOURCE);
+        let sum = analysis.definition(at("sum;")).unwrap();
+        assert_eq!(
+            (sum.kind.clone(), sum.start),
+            (SymbolKind::Variable, at("sum"))
+        );
+        let x = analysis.definition(at("x + y") + 1).unwrap();
+        assert_eq!(
+            (x.kind.clone(), x.start),
+            (SymbolKind::Parameter, at("x i32"))
+        );
+        let add = analysis.definition(at("add 1")).unwrap();
+        assert_eq!((add.kind.clone(), add.start), (SymbolKind::Function, 0));
+        assert!(analysis.definition(at("i32")).is_none());
+    }
+}
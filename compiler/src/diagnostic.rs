@@ -0,0 +1,111 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+// Compiler errors and warnings. The command line prints them as they happen and
+// exits on errors, while tools that must survive bad input, like the language
+// server, run the compiler inside `catch` to collect them instead.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: String,
+    pub message: String,
+    // Both count from 1, col in characters.
+    pub line: u32,
+    pub col: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}:{}): {}",
+            self.kind, self.line, self.col, self.message
+        )
+    }
+}
+
+thread_local! {
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    static REPORTED: RefCell<Vec<Diagnostic>> = const { RefCell::new(vec![]) };
+}
+
+pub fn fatal(kind: &str, line: u32, col: usize, message: String) -> ! {
+    let diagnostic = Diagnostic {
+        severity: Severity::Error,
+        kind: kind.to_string(),
+        message,
+        line,
+        col,
+    };
+    if CATCHING.with(|c| c.get()) {
+        // resume_unwind skips the panic hook so nothing is printed.
+        panic::resume_unwind(Box::new(diagnostic));
+    }
+    eprintln!("{}", diagnostic);
+    std::process::exit(1);
+}
+
+// An error the caller recovers from itself, like a failed C++ build that still
+// has to clean up before exiting with its own status.
+pub fn error(kind: &str, line: u32, col: usize, message: String) {
+    report(Severity::Error, kind, line, col, message);
+}
+
+pub fn warn(kind: &str, line: u32, col: usize, message: String) {
+    report(Severity::Warning, kind, line, col, message);
+}
+
+fn report(severity: Severity, kind: &str, line: u32, col: usize, message: String) {
+    let diagnostic = Diagnostic {
+        severity,
+        kind: kind.to_string(),
+        message,
+        line,
+        col,
+    };
+    if CATCHING.with(|c| c.get()) {
+        REPORTED.with(|w| w.borrow_mut().push(diagnostic));
+    } else {
+        eprintln!("{}", diagnostic);
+    }
+}
+
+// Runs `f`, returning its result when it finished without errors and every
+// diagnostic it raised. Internal compiler panics are reported as errors too.
+pub fn catch<T>(f: impl FnOnce() -> T) -> (Option<T>, Vec<Diagnostic>) {
+    let was_catching = CATCHING.with(|c| c.replace(true));
+    let outer_reported = REPORTED.with(|w| w.replace(vec![]));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(was_catching));
+    let mut diagnostics = REPORTED.with(|w| w.replace(outer_reported));
+
+    match result {
+        Ok(value) => (Some(value), diagnostics),
+        Err(payload) => {
+            let error = match payload.downcast::<Diagnostic>() {
+                Ok(diagnostic) => *diagnostic,
+                Err(payload) => Diagnostic {
+                    severity: Severity::Error,
+                    kind: String::from("Internal compiler error"),
+                    message: payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_default(),
+                    line: 1,
+                    col: 1,
+                },
+            };
+            diagnostics.push(error);
+            (None, diagnostics)
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::diagnostic;
+use crate::parse::{self, ParseTok, ParseType};
+use crate::{LexToken, Lexer, TokenType, Trivia, TriviaKind};
+use std::collections::HashMap;
+
+// Re-emits a source file in the canonical layout. The parse tree decides where
+// statements start and which ones have indented bodies, the tokens of each
+// statement are then written out with canonical spacing:
+//
+//   name: type: value          variable definitions
+//   name x i32, y i32: i32     function definitions, bodies indented up to `end`
+//   print! a, b;               calls
+//
+// Comments are kept, single blank lines between statements too.
+
+const INDENT: &str = "    ";
+
+struct Formatter {
+    out: Vec<String>,
+    // Source line of the last token or comment written.
+    last_line: u32,
+    // Nothing written yet in the current block, so no blank line is needed.
+    fresh: bool,
+}
+
+fn lex(source: &str) -> (Vec<LexToken>, Vec<Trivia>) {
+    let mut lexer = Lexer::new(source.chars().collect());
+    lexer.lex();
+    (lexer.tree(), lexer.trailing_trivia())
+}
+
+pub fn format(source: &str) -> String {
+    if source.trim().is_empty() {
+        return String::new();
+    }
+    let (tokens, trailing) = lex(source);
+    let mut f = Formatter {
+        out: vec![],
+        last_line: 0,
+        fresh: true,
+    };
+    if !tokens.is_empty() {
+        let mut parser = parse::Parser::new(tokens.clone(), source.to_string(), HashMap::new());
+        parser.init();
+        f.block(&parser.tree(), &tokens, 0);
+    }
+    f.comments(&trailing, 0);
+
+    let mut formatted = f.out.join("\n");
+    formatted.push('\n');
+    check_tokens(&tokens, &formatted);
+    formatted
+}
+
+// Formatting only ever changes trivia, anything else is a formatter bug that
+// must not reach the user's files.
+fn check_tokens(tokens: &[LexToken], formatted: &str) {
+    let (after, _) = lex(formatted);
+    let same = tokens.len() == after.len()
+        && tokens
+            .iter()
+            .zip(&after)
+            .all(|(a, b)| a.tok_type() == b.tok_type() && a.content() == b.content());
+    if !same {
+        diagnostic::fatal(
+            "Internal formatter error",
+            1,
+            1,
+            String::from("Formatting would change the meaning of the file."),
+        );
+    }
+}
+
+// Whitespace written between two tokens of a statement.
+fn space(before: Option<&LexToken>, prev: &LexToken, next: &LexToken) -> &'static str {
+    match (prev.tok_type(), next.tok_type()) {
+        (_, TokenType::RPAREN)
+        | (_, TokenType::COMMA)
+        | (_, TokenType::SEMCOLON)
+        | (_, TokenType::COLON)
+        | (TokenType::LPAREN, _)
+        | (TokenType::BITNOT, _) => "",
+        // print! and counter!, but not the ! of !(print x) or ret !(f x)
+        (TokenType::LABEL, TokenType::MMARK) if prev.content() != "ret" => "",
+        (TokenType::MMARK, TokenType::LPAREN)
+            if before.map(|b| b.tok_type()) != Some(&TokenType::LABEL)
+                || before.map(|b| b.content()) == Some("ret") =>
+        {
+            ""
+        }
+        // -x, but - 5 stays apart or it would lex as the number -5.
+        (TokenType::SUBBIN, next)
+            if next != &TokenType::NUMBER
+                && !matches!(
+                    before.map(|b| b.tok_type()),
+                    Some(
+                        TokenType::NUMBER
+                            | TokenType::NEGNUMBER
+                            | TokenType::LABEL
+                            | TokenType::STRING
+                            | TokenType::RPAREN
+                    )
+                ) =>
+        {
+            ""
+        }
+        // A space would turn 5-3 into 5 -3, which lexes the same but reads as
+        // two numbers.
+        (TokenType::NUMBER, TokenType::NEGNUMBER)
+        | (TokenType::LABEL, TokenType::NEGNUMBER)
+        | (TokenType::RPAREN, TokenType::NEGNUMBER) => "",
+        _ => " ",
+    }
+}
+
+impl Formatter {
+    fn emit(&mut self, depth: usize, text: String, first_line: u32, last_line: u32) {
+        if !self.fresh && first_line > self.last_line + 1 {
+            self.out.push(String::new());
+        }
+        self.out.push(format!("{}{}", INDENT.repeat(depth), text));
+        self.last_line = last_line;
+        self.fresh = false;
+    }
+
+    // Comments on the line of the last thing written stay at the end of it,
+    // others get a line of their own.
+    fn comments(&mut self, trivia: &[Trivia], depth: usize) {
+        for comment in trivia.iter().filter(|t| t.kind == TriviaKind::Comment) {
+            let text = comment.text.trim_end().to_string();
+            if comment.loc.line == self.last_line && !self.out.is_empty() {
+                let last = self.out.last_mut().unwrap();
+                last.push(' ');
+                last.push_str(&text);
+            } else {
+                self.emit(depth, text, comment.loc.line, comment.loc.line);
+            }
+        }
+    }
+
+    fn statement(&mut self, tokens: &[LexToken], depth: usize) {
+        self.comments(tokens[0].trivia(), depth);
+        let mut text = tokens[0].content().to_string();
+        let mut own_line = vec![];
+        let mut trailing = vec![];
+        for i in 1..tokens.len() {
+            for comment in tokens[i].trivia() {
+                if comment.kind != TriviaKind::Comment {
+                    continue;
+                }
+                if comment.loc.line == tokens[i - 1].line() {
+                    trailing.push(comment.text.trim_end().to_string());
+                } else {
+                    own_line.push(comment.clone());
+                }
+            }
+            let before = if i > 1 { Some(&tokens[i - 2]) } else { None };
+            text.push_str(space(before, &tokens[i - 1], &tokens[i]));
+            text.push_str(tokens[i].content());
+        }
+        // Statements are joined onto one line, comments from inside them go first.
+        self.comments(&own_line, depth);
+        for comment in trailing {
+            text.push(' ');
+            text.push_str(&comment);
+        }
+        let last_line = tokens.last().unwrap().end_line();
+        self.emit(depth, text, tokens[0].line(), last_line);
+    }
+
+    // A header line, the statements of a body and its `end`.
+    fn body(&mut self, tokens: &[LexToken], header: usize, statements: &[ParseTok], depth: usize) {
+        self.statement(&tokens[..header], depth);
+        let end = tokens.len() - 1;
+        self.fresh = true;
+        self.block(statements, &tokens[header..end], depth + 1);
+        self.comments(tokens[end].trivia(), depth + 1);
+        self.fresh = true;
+        self.emit(
+            depth,
+            tokens[end].content().to_string(),
+            tokens[end].line(),
+            tokens[end].line(),
+        );
+    }
+
+    fn block(&mut self, statements: &[ParseTok], tokens: &[LexToken], depth: usize) {
+        let starts: Vec<usize> = statements
+            .iter()
+            .map(|statement| {
+                tokens
+                    .iter()
+                    .position(|tok| tok.start() == statement.location.start_col)
+                    .unwrap()
+            })
+            .collect();
+        for (i, statement) in statements.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(tokens.len());
+            let tokens = &tokens[starts[i]..end];
+            match statement.tok_type {
+                ParseType::FNMAKE => {
+                    // The header ends with the return type after the first `:`.
+                    let colon = tokens
+                        .iter()
+                        .position(|tok| tok.tok_type() == &TokenType::COLON)
+                        .unwrap();
+                    let func = statement.fnmake.as_ref().as_ref().unwrap();
+                    self.body(tokens, colon + 2, &func.body, depth);
+                }
+                ParseType::TEST => {
+                    let test = statement.test.as_ref().as_ref().unwrap();
+                    self.body(tokens, 2, &test.body, depth);
+                }
+                _ => self.statement(tokens, depth),
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use crate::diagnostic;
 use crate::parse;
 
 use indexmap::IndexMap;
-use nanoid::nanoid;
+use std::cell::{Cell, RefCell};
 
 pub struct Function {
     name: String,
@@ -38,11 +39,17 @@ pub fn init_str_lit(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
         PrimType {
             def: "
 #include <string>
+#include <memory>
 struct STR_LIT {
-  int length;
-  std::string chs;
-  std::string display() { return chs; };
-  STR_LIT(std::string str) : chs(str){};
+  std::shared_ptr<const std::string> buffer;
+  const std::string& text() const { return *buffer; };
+  std::string display() const { return *buffer; };
+  STR_LIT() : buffer(empty()){};
+  STR_LIT(std::string str) : buffer(std::make_shared<const std::string>(std::move(str))){};
+  static const std::shared_ptr<const std::string>& empty() {
+    static const std::shared_ptr<const std::string> none = std::make_shared<const std::string>();
+    return none;
+  };
 };
             "
             .to_string(),
@@ -61,7 +68,9 @@ pub fn init_int_lit(definitions: &mut IndexMap<parse::Primitives, PrimType>, siz
                 + &size
                 + "_LIT {\nint_fast"
                 + &size
-                + "_t num;\nstd::string display() { return std::to_string(num); };\nINT"
+                + "_t num;\nstd::string display() const { return std::to_string(num); };\nINT"
+                + &size
+                + "_LIT() : num(0){};\nINT"
                 + &size
                 + "_LIT(int_fast"
                 + &size
@@ -74,107 +83,790 @@ pub fn init_int_lit(definitions: &mut IndexMap<parse::Primitives, PrimType>, siz
 }
 
 pub fn init_float_lit(definitions: &mut IndexMap<parse::Primitives, PrimType>, size: String) {
-    if String::from("32") == size {
-        definitions.insert(
-            parse::Primitives::FLOAT(32),
-            PrimType {
-                def: "\n#include <string>\nstruct FLOAT".to_owned()
-                    + &size
-                    + "_LIT {\nfloat num;\nstd::string display() { return std::to_string(num); };\nFLOAT"
-                    + &size
-                    + "_LIT(float f) : num(f){};\n};",
-                name: String::from("FLOAT".to_owned() + &size + "_LIT"),
+    let num_type = match size.as_str() {
+        "32" => "float",
+        "64" => "double",
+        _ => return,
+    };
+    definitions.insert(
+        parse::Primitives::FLOAT(size.parse::<i8>().unwrap()),
+        PrimType {
+            def: "\n#include <string>\nstruct FLOAT".to_owned()
+                + &size
+                + "_LIT {\n"
+                + num_type
+                + " num;\nstd::string display() const { return std::to_string(num); };\nFLOAT"
+                + &size
+                + "_LIT() : num(0){};\nFLOAT"
+                + &size
+                + "_LIT("
+                + num_type
+                + " f) : num(f){};\n};",
+            name: "FLOAT".to_owned() + &size + "_LIT",
             raw: None,
             ext: true,
-            },
-        );
-    }
+        },
+    );
 }
 
+// Operator helpers, {TYPE}{size}_{OP} taking both operands. Prefix operators
+// get a 0 on the left. Whole number helpers check for overflow, division by
+// zero and shifts past the width of the type unless checks are turned off,
+// and then also take the location to panic with.
 pub fn init_fn_math(
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
     size: String,
     type_: String,
 ) {
     let first_letter = type_.as_bytes()[0].to_owned() as char;
+    let checked = type_ != "FLOAT" && checks_enabled();
+    let helpers: Vec<(&str, String)> = if type_ == "FLOAT" {
+        // fmodf and powf for f32, fmod and pow for f64.
+        let suffix = if size == "32" { "f" } else { "" };
+        vec![
+            ("PLUS", String::from("return x.num + y.num;")),
+            ("SUB", String::from("return x.num - y.num;")),
+            ("MUL", String::from("return x.num * y.num;")),
+            ("DIV", String::from("return x.num / y.num;")),
+            ("NEG", String::from("return -y.num;")),
+            ("MOD", format!("return fmod{}(x.num, y.num);", suffix)),
+            ("POW", format!("return pow{}(x.num, y.num);", suffix)),
+        ]
+    } else if checked {
+        init_fn_panic(definitions);
+        // The fast types can be wider than the size, so results are worked
+        // out in long long and compared against the limits of the size.
+        let out_of_range = format!("out < INT{s}_MIN || out > INT{s}_MAX", s = size);
+        let overflow = |op: &str| format!("som_panic(\"i{} overflow in {}\", at);", size, op);
+        let arithmetic = |builtin: &str, op: &str| {
+            format!(
+                "long long out;\nif (__builtin_{builtin}_overflow((long long)x.num, (long long)y.num, &out) || {range}) {panic}\nreturn out;",
+                builtin = builtin,
+                range = out_of_range,
+                panic = overflow(op)
+            )
+        };
+        let shift_range = format!(
+            "if (y.num < 0 || y.num >= {s}) som_panic(\"shift by \" + std::to_string(y.num) + \" is out of range for i{s}\", at);\n",
+            s = size
+        );
+        vec![
+            ("PLUS", arithmetic("add", "+")),
+            ("SUB", arithmetic("sub", "-")),
+            ("MUL", arithmetic("mul", "*")),
+            (
+                "DIV",
+                format!(
+                    "if (y.num == 0) som_panic(\"division by zero\", at);\nif (x.num == INT{s}_MIN && y.num == -1) {panic}\nreturn x.num / y.num;",
+                    s = size,
+                    panic = overflow("/")
+                ),
+            ),
+            (
+                "NEG",
+                format!(
+                    "if (y.num == INT{s}_MIN) {panic}\nreturn -y.num;",
+                    s = size,
+                    panic = overflow("-")
+                ),
+            ),
+            (
+                "MOD",
+                String::from(
+                    "if (y.num == 0) som_panic(\"remainder by zero\", at);\nif (y.num == -1) return 0;\nreturn x.num % y.num;",
+                ),
+            ),
+            ("BITAND", String::from("return x.num & y.num;")),
+            ("BITOR", String::from("return x.num | y.num;")),
+            ("BITXOR", String::from("return x.num ^ y.num;")),
+            ("BITNOT", String::from("return ~y.num;")),
+            (
+                "SHL",
+                format!(
+                    "{check}long long out = (long long)((unsigned long long)x.num << y.num);\nif ((out >> y.num) != x.num || {range}) {panic}\nreturn out;",
+                    check = shift_range,
+                    range = out_of_range,
+                    panic = overflow("<<")
+                ),
+            ),
+            ("SHR", format!("{}return x.num >> y.num;", shift_range)),
+            ("NOT", String::from("return y.num == 0;")),
+            (
+                "POW",
+                // By squaring, the base is only squared while a bit of the power
+                // is left to multiply it in, so it overflows only when the
+                // result would.
+                format!(
+                    "if (y.num < 0) return x.num == 1 ? 1 : x.num == -1 ? (y.num % 2 ? -1 : 1) : 0;\n\
+                     long long out = 1;\nlong long base = x.num;\n\
+                     for (decltype(y.num) n = y.num; n > 0; n >>= 1) {{\n\
+                     if ((n & 1) && (__builtin_mul_overflow(out, base, &out) || {range})) {panic}\n\
+                     if (n > 1 && (__builtin_mul_overflow(base, base, &base) || {base_range})) {panic}\n}}\nreturn out;",
+                    range = out_of_range,
+                    base_range = out_of_range.replace("out", "base"),
+                    panic = overflow("**")
+                ),
+            ),
+        ]
+    } else {
+        vec![
+            ("PLUS", String::from("return x.num + y.num;")),
+            ("SUB", String::from("return x.num - y.num;")),
+            ("MUL", String::from("return x.num * y.num;")),
+            ("DIV", String::from("return x.num / y.num;")),
+            ("NEG", String::from("return -y.num;")),
+            ("MOD", String::from("return x.num % y.num;")),
+            ("BITAND", String::from("return x.num & y.num;")),
+            ("BITOR", String::from("return x.num | y.num;")),
+            ("BITXOR", String::from("return x.num ^ y.num;")),
+            ("BITNOT", String::from("return ~y.num;")),
+            ("SHL", String::from("return x.num << y.num;")),
+            ("SHR", String::from("return x.num >> y.num;")),
+            ("NOT", String::from("return y.num == 0;")),
+            // Whole powers only, 2 ** -1 is 0 like 1 / 2.
+            (
+                "POW",
+                String::from(
+                    "if (y.num < 0) return x.num == 1 ? 1 : x.num == -1 ? (y.num % 2 ? -1 : 1) : 0;\n\
+                     decltype(x.num) out = 1;\ndecltype(x.num) base = x.num;\n\
+                     for (decltype(y.num) n = y.num; n > 0; n >>= 1) {\n\
+                     if (n & 1) out *= base;\nif (n > 1) base *= base;\n}\nreturn out;",
+                ),
+            ),
+        ]
+    };
+    for (op, body) in helpers {
+        let include = match op {
+            "MOD" | "POW" => "#include <cmath>\n",
+            _ => "",
+        };
+        definitions.insert(
+            parse::Primitives::INSCOPE(format!("{}{}_{}", first_letter, size, op)),
+            PrimType {
+                def: format!(
+                    "{include}{TYPE}{size}_LIT {TYPE}{size}_{op}({TYPE}{size}_LIT x, {TYPE}{size}_LIT y{at}) {{\n{body}}};",
+                    include = include,
+                    size = size,
+                    TYPE = type_,
+                    op = op,
+                    at = if checked { ", const char* at" } else { "" },
+                    body = body
+                ),
+                name: type_.clone() + &size + "_LIT",
+                raw: None,
+                ext: true,
+            },
+        );
+    }
+}
+
+fn init_lib(definitions: &mut IndexMap<parse::Primitives, PrimType>, tok_type: parse::Primitives) {
+    if parse::prim_eq(&tok_type, &parse::Primitives::INT(32)) {
+        let variant = prim_var_str(tok_type);
+        init_int_lit(definitions, variant[1].clone());
+        init_fn_math(definitions, variant[1].clone(), variant[0].clone());
+    } else if parse::prim_eq(&tok_type, &parse::Primitives::FLOAT(32)) {
+        let variant = prim_var_str(tok_type);
+        init_float_lit(definitions, variant[1].clone());
+        init_fn_math(definitions, variant[1].clone(), variant[0].clone());
+    } else if parse::prim_eq(&tok_type, &parse::Primitives::STRING) {
+        init_str_lit(definitions);
+    }
+}
+
+pub fn init_fn_io(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
     definitions.insert(
-        parse::Primitives::INSCOPE(String::from(first_letter) + &size+"_PLUS"),
+        parse::Primitives::INSCOPE("print".to_string()),
         PrimType {
             def: format!(
-                "{TYPE}{size}_LIT {TYPE}{size}_PLUS({TYPE}{size}_LIT x, {TYPE}{size}_LIT y) {{\nreturn x.num + y.num;}};", size=size, TYPE=type_
-                 ),
-            name: String::from(type_.clone()+ &size + "_LIT"),
+                "
+#include <cstdio>
+int print(std::vector<STR_LIT>* ARGS) {{
+  for (const STR_LIT& arg : *ARGS) {{
+    fwrite(arg.text().data(), 1, arg.text().size(), stdout);
+  }}
+  return 0;
+}}
+int eprint(std::vector<STR_LIT>* ARGS) {{
+  fflush(stdout);
+  for (const STR_LIT& arg : *ARGS) {{
+    fwrite(arg.text().data(), 1, arg.text().size(), stderr);
+  }}
+  return 0;
+}}
+                "
+            )
+            .to_string(),
+            name: "print".to_string(),
             raw: None,
             ext: true,
         },
     );
+}
+
+// Std functions working on strings, indexes count bytes.
+pub fn init_fn_strings(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_str_lit(definitions);
+    init_fn_panic(definitions);
+    init_int_lit(definitions, String::from("32"));
+    init_float_lit(definitions, String::from("32"));
     definitions.insert(
-        parse::Primitives::INSCOPE(String::from(first_letter)+ &size+"_SUB"),
+        parse::Primitives::INSCOPE("strings".to_string()),
         PrimType {
-            def: format!(
-                "{TYPE}{size}_LIT {TYPE}{size}_SUB({TYPE}{size}_LIT x, {TYPE}{size}_LIT y) {{\nreturn x.num - y.num;}};", size=size, TYPE=type_
-            ),
-            name: String::from(type_.clone()+ &size + "_LIT"),
+            def: "
+#include <algorithm>
+#include <cctype>
+#include <cerrno>
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+INT32_LIT som_len(STR_LIT s) { return INT32_LIT(s.text().size()); }
+STR_LIT som_concat(STR_LIT a, STR_LIT b) { return STR_LIT(a.text() + b.text()); }
+STR_LIT som_substring(STR_LIT s, INT32_LIT start, INT32_LIT length) {
+  long from = std::min(std::max((long)start.num, 0L), (long)s.text().size());
+  return STR_LIT(s.text().substr(from, std::max((long)length.num, 0L)));
+}
+STR_LIT som_split(STR_LIT s, STR_LIT sep, INT32_LIT index) {
+  if (index.num < 0 || (sep.text().empty() && index.num > 0)) return STR_LIT(\"\");
+  size_t start = 0;
+  for (long i = 0; i < index.num; i++) {
+    size_t found = s.text().find(sep.text(), start);
+    if (found == std::string::npos) return STR_LIT(\"\");
+    start = found + sep.text().size();
+  }
+  size_t end = sep.text().empty() ? std::string::npos : s.text().find(sep.text(), start);
+  return STR_LIT(s.text().substr(start, end == std::string::npos ? end : end - start));
+}
+STR_LIT som_join(STR_LIT sep, std::vector<STR_LIT> pieces) {
+  std::string out;
+  for (size_t i = 0; i < pieces.size(); i++) {
+    if (i > 0) out += sep.text();
+    out += pieces[i].text();
+  }
+  return STR_LIT(out);
+}
+STR_LIT som_trim(STR_LIT s) {
+  size_t start = s.text().find_first_not_of(\" \\t\\r\\n\\v\\f\");
+  if (start == std::string::npos) return STR_LIT(\"\");
+  size_t end = s.text().find_last_not_of(\" \\t\\r\\n\\v\\f\");
+  return STR_LIT(s.text().substr(start, end - start + 1));
+}
+INT32_LIT som_contains(STR_LIT s, STR_LIT part) {
+  return INT32_LIT(s.text().find(part.text()) != std::string::npos);
+}
+STR_LIT som_replace(STR_LIT s, STR_LIT from, STR_LIT to) {
+  if (from.text().empty()) return s;
+  std::string out;
+  size_t start = 0;
+  size_t found;
+  while ((found = s.text().find(from.text(), start)) != std::string::npos) {
+    out += s.text().substr(start, found - start) + to.text();
+    start = found + from.text().size();
+  }
+  return STR_LIT(out + s.text().substr(start));
+}
+STR_LIT som_to_upper(STR_LIT s) {
+  std::string out = s.text();
+  for (auto& ch : out) ch = toupper((unsigned char)ch);
+  return STR_LIT(out);
+}
+STR_LIT som_to_lower(STR_LIT s) {
+  std::string out = s.text();
+  for (auto& ch : out) ch = tolower((unsigned char)ch);
+  return STR_LIT(out);
+}
+[[noreturn]] void som_not_a_number(const char* fn, STR_LIT s, const char* at) {
+  som_panic(std::string(fn) + \": \\\"\" + s.text() + \"\\\" is not a number\", at);
+}
+INT32_LIT som_parse_int(STR_LIT s, const char* at) {
+  const char* start = s.text().c_str();
+  char* end;
+  errno = 0;
+  long value = strtol(start, &end, 10);
+  if (end == start || *end != '\\0' || errno == ERANGE || value < INT32_MIN || value > INT32_MAX) {
+    som_not_a_number(\"parse_int\", s, at);
+  }
+  return INT32_LIT(value);
+}
+FLOAT32_LIT som_parse_float(STR_LIT s, const char* at) {
+  const char* start = s.text().c_str();
+  char* end;
+  float value = strtof(start, &end);
+  if (end == start || *end != '\\0') {
+    som_not_a_number(\"parse_float\", s, at);
+  }
+  return FLOAT32_LIT(value);
+}
+template <typename T> STR_LIT som_to_string(T value) { return STR_LIT(value.display()); }
+            "
+            .to_string(),
+            name: "strings".to_string(),
             raw: None,
             ext: true,
         },
     );
+}
+
+// Std math functions. Most take any number and work in f32, abs, min, max,
+// clamp and pow keep the type of their arguments. f64 arguments get helpers
+// of their own from init_fn_math64.
+pub fn init_fn_math_module(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_lib(definitions, parse::Primitives::INT(32));
+    init_lib(definitions, parse::Primitives::FLOAT(32));
+    // Checked whole powers take the location of the call, like the operator.
+    let pow = match checks_enabled() {
+        true => {
+            "
+INT32_LIT som_pow(INT32_LIT x, INT32_LIT y, const char* at) { return INT32_POW(x, y, at); }
+FLOAT32_LIT som_pow(FLOAT32_LIT x, FLOAT32_LIT y, const char*) { return FLOAT32_POW(x, y); }"
+        }
+        false => {
+            "
+INT32_LIT som_pow(INT32_LIT x, INT32_LIT y) { return INT32_POW(x, y); }
+FLOAT32_LIT som_pow(FLOAT32_LIT x, FLOAT32_LIT y) { return FLOAT32_POW(x, y); }"
+        }
+    };
     definitions.insert(
-        parse::Primitives::INSCOPE(String::from(first_letter)+ &size+"_MUL"),
+        parse::Primitives::INSCOPE("math".to_string()),
         PrimType {
-            def: format!(
-                "{TYPE}{size}_LIT {TYPE}{size}_MUL({TYPE}{size}_LIT x, {TYPE}{size}_LIT y) {{\nreturn x.num * y.num;}};", size=size, TYPE=type_
-            ),
-            name: String::from(type_.clone()+ &size + "_LIT"),
+            def: pow.to_owned()
+                + "
+#include <cmath>
+template <typename T> T som_abs(T x) { return T(x.num < 0 ? -x.num : x.num); }
+template <typename T> T som_min(T a, T b) { return T(b.num < a.num ? b.num : a.num); }
+template <typename T> T som_max(T a, T b) { return T(b.num > a.num ? b.num : a.num); }
+template <typename T> T som_clamp(T x, T low, T high) {
+  return T(x.num < low.num ? low.num : x.num > high.num ? high.num : x.num);
+}
+template <typename T> FLOAT32_LIT som_sqrt(T x) { return FLOAT32_LIT(sqrtf(x.num)); }
+template <typename T> FLOAT32_LIT som_floor(T x) { return FLOAT32_LIT(floorf(x.num)); }
+template <typename T> FLOAT32_LIT som_ceil(T x) { return FLOAT32_LIT(ceilf(x.num)); }
+template <typename T> FLOAT32_LIT som_round(T x) { return FLOAT32_LIT(roundf(x.num)); }
+template <typename T> FLOAT32_LIT som_sin(T x) { return FLOAT32_LIT(sinf(x.num)); }
+template <typename T> FLOAT32_LIT som_cos(T x) { return FLOAT32_LIT(cosf(x.num)); }
+template <typename T> FLOAT32_LIT som_tan(T x) { return FLOAT32_LIT(tanf(x.num)); }
+template <typename T> FLOAT32_LIT som_asin(T x) { return FLOAT32_LIT(asinf(x.num)); }
+template <typename T> FLOAT32_LIT som_acos(T x) { return FLOAT32_LIT(acosf(x.num)); }
+template <typename T> FLOAT32_LIT som_atan(T x) { return FLOAT32_LIT(atanf(x.num)); }
+template <typename T, typename U> FLOAT32_LIT som_atan2(T y, U x) {
+  return FLOAT32_LIT(atan2f(y.num, x.num));
+}
+template <typename T> FLOAT32_LIT som_exp(T x) { return FLOAT32_LIT(expf(x.num)); }
+template <typename T> FLOAT32_LIT som_ln(T x) { return FLOAT32_LIT(logf(x.num)); }
+template <typename T> FLOAT32_LIT som_log10(T x) { return FLOAT32_LIT(log10f(x.num)); }
+FLOAT32_LIT som_pi() { return FLOAT32_LIT(3.14159265358979f); }
+FLOAT32_LIT som_e() { return FLOAT32_LIT(2.71828182845905f); }
+            ",
+            name: "math".to_string(),
             raw: None,
             ext: true,
         },
     );
+}
+
+// The math functions working in f64, for calls with an f64 argument. They are
+// preferred over the f32 templates because they are not templates.
+pub fn init_fn_math64(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_lib(definitions, parse::Primitives::FLOAT(64));
+    let mut def = vec![String::from("#include <cmath>")];
+    for (name, c_name) in [
+        ("sqrt", "sqrt"),
+        ("floor", "floor"),
+        ("ceil", "ceil"),
+        ("round", "round"),
+        ("sin", "sin"),
+        ("cos", "cos"),
+        ("tan", "tan"),
+        ("asin", "asin"),
+        ("acos", "acos"),
+        ("atan", "atan"),
+        ("exp", "exp"),
+        ("ln", "log"),
+        ("log10", "log10"),
+    ] {
+        def.push(format!(
+            "FLOAT64_LIT som_{}(FLOAT64_LIT x) {{ return FLOAT64_LIT({}(x.num)); }}",
+            name, c_name
+        ));
+    }
+    def.push(String::from(
+        "FLOAT64_LIT som_atan2(FLOAT64_LIT y, FLOAT64_LIT x) { return FLOAT64_LIT(atan2(y.num, x.num)); }
+template <typename T> FLOAT64_LIT som_atan2(FLOAT64_LIT y, T x) { return FLOAT64_LIT(atan2(y.num, x.num)); }
+template <typename T> FLOAT64_LIT som_atan2(T y, FLOAT64_LIT x) { return FLOAT64_LIT(atan2(y.num, x.num)); }",
+    ));
     definitions.insert(
-        parse::Primitives::INSCOPE(String::from(first_letter)+ &size+"_DIV"),
+        parse::Primitives::INSCOPE("math64".to_string()),
+        PrimType {
+            def: def.join("\n"),
+            name: "math64".to_string(),
+            raw: None,
+            ext: true,
+        },
+    );
+}
+
+// abs of the smallest whole number of a size does not fit in it, so checked
+// builds give each size an abs that panics instead, taking the location.
+pub fn init_fn_abs(definitions: &mut IndexMap<parse::Primitives, PrimType>, size: String) {
+    init_fn_panic(definitions);
+    definitions.insert(
+        parse::Primitives::INSCOPE(format!("I{}_ABS", size)),
         PrimType {
             def: format!(
-                "{TYPE}{size}_LIT {TYPE}{size}_DIV({TYPE}{size}_LIT x, {TYPE}{size}_LIT y) {{\nreturn x.num / y.num;}};", size=size, TYPE=type_
+                "INT{s}_LIT som_abs(INT{s}_LIT x, const char* at) {{\nif (x.num == INT{s}_MIN) som_panic(\"i{s} overflow in abs\", at);\nreturn x.num < 0 ? -x.num : x.num;\n}}",
+                s = size
             ),
-            name: String::from(type_.clone()+ &size + "_LIT"),
+            name: format!("INT{}_LIT", size),
             raw: None,
             ext: true,
         },
     );
 }
 
-fn init_lib(definitions: &mut IndexMap<parse::Primitives, PrimType>, tok_type: parse::Primitives) {
-    if parse::prim_eq(&tok_type, &parse::Primitives::INT(32)) {
-        let variant = prim_var_str(tok_type);
-        init_int_lit(definitions, variant[1].clone());
-        init_fn_math(definitions, variant[1].clone(), variant[0].clone());
-    } else if parse::prim_eq(&tok_type, &parse::Primitives::FLOAT(32)) {
-        let variant = prim_var_str(tok_type);
-        init_float_lit(definitions, variant[1].clone());
-        init_fn_math(definitions, variant[1].clone(), variant[0].clone());
-    } else if parse::prim_eq(&tok_type, &parse::Primitives::STRING) {
-        init_str_lit(definitions);
-    }
+// Stopping the program with a message on stderr, and the backtrace in debug
+// builds. Inside a test the test fails instead. Panics give the location in
+// the source and exit with 101.
+pub fn init_fn_panic(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    let backtrace = match backtraces_enabled() {
+        true => {
+            init_fn_backtrace(definitions);
+            "\n  som_print_backtrace();"
+        }
+        false => "",
+    };
+    definitions.insert(
+        parse::Primitives::INSCOPE("panic".to_string()),
+        PrimType {
+            def: "
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+#include <iostream>
+#include <string>
+struct SOM_TEST_FAILURE {
+  std::string message;
+};
+bool SOM_IN_TEST = false;
+[[noreturn]] void som_fail(std::string message, int status) {
+  if (SOM_IN_TEST) {
+    throw SOM_TEST_FAILURE{message};
+  }
+  fflush(stdout);
+  std::cerr << message << std::endl;"
+                .to_owned()
+                + backtrace
+                + "
+  exit(status);
+}
+[[noreturn]] void som_panic(std::string message, const char* location) {
+  som_fail(std::string(\"panic (\") + location + \"): \" + message, 101);
+}
+            ",
+            name: "panic".to_string(),
+            raw: None,
+            ext: true,
+        },
+    );
 }
 
-pub fn init_fn_io(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+// Checks for conversions with `as` that can fail, a value that does not fit
+// the type or a string that is not a number panics.
+pub fn init_fn_conversions(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_fn_panic(definitions);
     definitions.insert(
-        parse::Primitives::INSCOPE("print".to_string()),
+        parse::Primitives::INSCOPE("conversions".to_string()),
         PrimType {
-            def: format!(
-                "
+            def: "
+#include <cerrno>
+#include <cmath>
+[[noreturn]] void som_bad_conversion(std::string shown, const char* type, const char* location) {
+  som_panic(\"cannot convert \" + shown + \" to \" + type, location);
+}
+long long som_to_whole(long double value, long long low, long long high, const char* type,
+                       std::string shown, const char* location) {
+  long double whole = truncl(value);
+  if (whole != whole || whole < low || whole > high) som_bad_conversion(shown, type, location);
+  return (long long)whole;
+}
+long long som_str_to_whole(std::string text, long long low, long long high, const char* type,
+                           const char* location) {
+  const char* start = text.c_str();
+  char* end = nullptr;
+  errno = 0;
+  long long value = strtoll(start, &end, 10);
+  if (text.empty() || *end != '\\0' || errno == ERANGE || value < low || value > high) {
+    som_bad_conversion(\"\\\"\" + text + \"\\\"\", type, location);
+  }
+  return value;
+}
+long double som_str_to_float(std::string text, const char* type, const char* location) {
+  const char* start = text.c_str();
+  char* end = nullptr;
+  long double value = strtold(start, &end);
+  if (text.empty() || *end != '\\0') som_bad_conversion(\"\\\"\" + text + \"\\\"\", type, location);
+  return value;
+}
+            "
+            .to_string(),
+            name: "conversions".to_string(),
+            raw: None,
+            ext: true,
+        },
+    );
+}
+
+// Reading and writing files and stdin. Calls that fail give back an empty
+// string or 1 and leave a message for io_error, calls that work clear it.
+pub fn init_fn_files(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_str_lit(definitions);
+    init_int_lit(definitions, String::from("32"));
+    definitions.insert(
+        parse::Primitives::INSCOPE("files".to_string()),
+        PrimType {
+            def: "
+#include <cerrno>
 #include <cstdio>
-int print(std::vector<std::unique_ptr<STR_LIT>>* ARGS) {{
-  for (int i = 0; i < ARGS->size(); i++) {{
-    STR_LIT t = *ARGS->at(i);
-    printf(\"%s\", t.chs.c_str());
-  }}
-  return 0;
-}}
-                "
-            )
+#include <cstring>
+#include <fstream>
+#include <iostream>
+#include <sstream>
+std::string som_io_message;
+STR_LIT som_io_error() { return STR_LIT(som_io_message); }
+STR_LIT som_io_done(std::string value) {
+  som_io_message = \"\";
+  return STR_LIT(value);
+}
+STR_LIT som_io_fail(std::string message) {
+  som_io_message = message;
+  return STR_LIT(\"\");
+}
+STR_LIT som_read_line() {
+  fflush(stdout);
+  std::string line;
+  if (!std::getline(std::cin, line)) return som_io_fail(\"end of input\");
+  if (!line.empty() && line.back() == '\\r') line.pop_back();
+  return som_io_done(line);
+}
+STR_LIT som_read_all() {
+  std::stringstream buffer;
+  buffer << std::cin.rdbuf();
+  return som_io_done(buffer.str());
+}
+STR_LIT som_read_file(STR_LIT path) {
+  std::ifstream file(path.text(), std::ios::binary);
+  if (!file) return som_io_fail(path.text() + \": \" + strerror(errno));
+  std::stringstream buffer;
+  buffer << file.rdbuf();
+  return som_io_done(buffer.str());
+}
+INT32_LIT som_write(STR_LIT path, STR_LIT content, std::ios::openmode mode) {
+  std::ofstream file(path.text(), std::ios::binary | mode);
+  if (!file || !(file << content.text()) || !file.flush()) {
+    som_io_fail(path.text() + \": \" + strerror(errno));
+    return INT32_LIT(1);
+  }
+  som_io_done(\"\");
+  return INT32_LIT(0);
+}
+INT32_LIT som_write_file(STR_LIT path, STR_LIT content) {
+  return som_write(path, content, std::ios::trunc);
+}
+INT32_LIT som_append_file(STR_LIT path, STR_LIT content) {
+  return som_write(path, content, std::ios::app);
+}
+            "
             .to_string(),
-            name: "print".to_string(),
+            name: "files".to_string(),
+            raw: None,
+            ext: true,
+        },
+    );
+}
+
+// Arguments, environment and exit status of the program. The arguments are
+// handed over by main, argument 0 is the first one after the program name.
+pub fn init_fn_process(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_str_lit(definitions);
+    init_int_lit(definitions, String::from("32"));
+    definitions.insert(
+        parse::Primitives::INSCOPE("process".to_string()),
+        PrimType {
+            def: "
+#include <cstdlib>
+int som_argc = 0;
+char** som_argv = nullptr;
+void som_set_args(int argc, char** argv) {
+  som_argc = argc;
+  som_argv = argv;
+}
+INT32_LIT som_arg_count() { return INT32_LIT(som_argc > 0 ? som_argc - 1 : 0); }
+STR_LIT som_arg(INT32_LIT n) {
+  if (n.num < 0 || n.num + 1 >= som_argc) return STR_LIT(\"\");
+  return STR_LIT(som_argv[n.num + 1]);
+}
+STR_LIT som_env(STR_LIT name) {
+  const char* value = getenv(name.text().c_str());
+  return STR_LIT(value ? value : \"\");
+}
+void som_exit(INT32_LIT code) { exit(code.num); }
+            "
+            .to_string(),
+            name: "process".to_string(),
+            raw: None,
+            ext: true,
+        },
+    );
+}
+
+// Statements main runs before anything else.
+pub fn main_setup(definitions: &IndexMap<parse::Primitives, PrimType>) -> Vec<String> {
+    let mut setup = vec![];
+    if definitions.contains_key(&parse::Primitives::INSCOPE("backtrace".to_string())) {
+        setup.push(String::from("som_install_crash_handler();"));
+    }
+    if definitions.contains_key(&parse::Primitives::INSCOPE("process".to_string())) {
+        setup.push(String::from("som_set_args(argc, argv);"));
+    }
+    setup
+}
+
+// The shadow call stack: every call of a user function pushes its name and
+// where it was called from for as long as it runs. Panics and crashes print
+// it, most recent call first, with recursion folded into a single line. Deep
+// stacks keep their oldest and newest calls and drop the ones in between.
+pub fn init_fn_backtrace(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    definitions.insert(
+        parse::Primitives::INSCOPE("backtrace".to_string()),
+        PrimType {
+            def: "
+#include <csignal>
+#include <cstring>
+#include <unistd.h>
+struct SOM_FRAME {
+  const char* name;
+  const char* location;
+};
+const int SOM_STACK_OLDEST = 64;
+const int SOM_STACK_NEWEST = 4096;
+SOM_FRAME som_oldest[SOM_STACK_OLDEST];
+SOM_FRAME som_newest[SOM_STACK_NEWEST];
+int som_depth = 0;
+struct SOM_CALL {
+  SOM_CALL(const char* name, const char* location) {
+    SOM_FRAME frame = {name, location};
+    if (som_depth < SOM_STACK_OLDEST) {
+      som_oldest[som_depth] = frame;
+    } else {
+      som_newest[som_depth % SOM_STACK_NEWEST] = frame;
+    }
+    som_depth++;
+  }
+  ~SOM_CALL() { som_depth--; }
+};
+bool som_recorded(int i) { return i < SOM_STACK_OLDEST || i >= som_depth - SOM_STACK_NEWEST; }
+SOM_FRAME som_frame_at(int i) {
+  return i < SOM_STACK_OLDEST ? som_oldest[i] : som_newest[i % SOM_STACK_NEWEST];
+}
+bool som_same_frame(SOM_FRAME a, SOM_FRAME b) {
+  return a.name == b.name && a.location == b.location;
+}
+// Only write(2) from here on, these run inside signal handlers too, so
+// numbers are written out by hand instead of with snprintf.
+void som_write_err(const char* text) {
+  ssize_t written = write(2, text, strlen(text));
+  (void)written;
+}
+void som_write_count(int count) {
+  char digits[16];
+  int i = sizeof digits - 1;
+  digits[i] = '\\0';
+  do {
+    digits[--i] = '0' + count % 10;
+    count /= 10;
+  } while (count > 0);
+  som_write_err(digits + i);
+}
+void som_print_frame(SOM_FRAME frame, int times) {
+  som_write_err(\"  \");
+  som_write_err(frame.name);
+  if (frame.location) {
+    som_write_err(\" called at \");
+    som_write_err(frame.location);
+  } else {
+    som_write_err(\" run after the top level code\");
+  }
+  if (times > 1) {
+    som_write_err(\" (\");
+    som_write_count(times);
+    som_write_err(\" times)\");
+  }
+  som_write_err(\"\\n\");
+}
+void som_print_backtrace() {
+  if (som_depth == 0) return;
+  som_write_err(\"backtrace, most recent call first:\\n\");
+  int lines = 0;
+  int i = som_depth - 1;
+  while (i >= 0) {
+    if (lines == 20) {
+      som_write_err(\"  ... and \");
+      som_write_count(i + 1);
+      som_write_err(\" older calls\\n\");
+      return;
+    }
+    if (!som_recorded(i)) {
+      som_write_err(\"  ... \");
+      som_write_count(i - SOM_STACK_OLDEST + 1);
+      som_write_err(\" calls not recorded\\n\");
+      i = SOM_STACK_OLDEST - 1;
+      lines++;
+      continue;
+    }
+    SOM_FRAME frame = som_frame_at(i);
+    int times = 0;
+    while (i >= 0 && som_recorded(i) && som_same_frame(som_frame_at(i), frame)) {
+      times++;
+      i--;
+    }
+    som_print_frame(frame, times);
+    lines++;
+  }
+}
+const char* som_signal_name(int sig) {
+  switch (sig) {
+    case SIGSEGV: return \"SIGSEGV\";
+    case SIGFPE: return \"SIGFPE\";
+    case SIGILL: return \"SIGILL\";
+    case SIGBUS: return \"SIGBUS\";
+    case SIGABRT: return \"SIGABRT\";
+    default: return \"a signal\";
+  }
+}
+// Flushing stdout is not safe here, so output it still buffers is lost.
+void som_crash(int sig) {
+  som_write_err(\"crashed with \");
+  som_write_err(som_signal_name(sig));
+  if (sig == SIGSEGV && som_depth > 1000) som_write_err(\", the call stack is probably too deep\");
+  som_write_err(\"\\n\");
+  som_print_backtrace();
+  raise(sig);
+}
+// Handlers run on their own stack so a stack overflow can still be reported,
+// and are reset first so raising the signal again ends the program.
+void som_install_crash_handler() {
+  static char handler_stack[1 << 16];
+  stack_t alternate = {};
+  alternate.ss_sp = handler_stack;
+  alternate.ss_size = sizeof handler_stack;
+  sigaltstack(&alternate, nullptr);
+  struct sigaction action = {};
+  action.sa_handler = som_crash;
+  action.sa_flags = SA_ONSTACK | SA_RESETHAND;
+  for (int sig : {SIGSEGV, SIGFPE, SIGILL, SIGBUS, SIGABRT}) sigaction(sig, &action, nullptr);
+}
+            "
+            .to_string(),
+            name: "backtrace".to_string(),
             raw: None,
             ext: true,
         },
@@ -199,6 +891,47 @@ pub fn init_fn_include(definitions: &mut IndexMap<parse::Primitives, PrimType>,
     );
 }
 
+pub fn init_fn_test(definitions: &mut IndexMap<parse::Primitives, PrimType>) {
+    init_str_lit(definitions);
+    init_fn_panic(definitions);
+    definitions.insert(
+        parse::Primitives::INSCOPE("test".to_string()),
+        PrimType {
+            def: "
+#include <cstdio>
+#include <functional>
+void som_assert_fail(std::string message) { som_fail(message, 1); }
+struct SOM_TEST {
+  std::string name;
+  std::function<void()> body;
+};
+int som_run_tests(std::vector<SOM_TEST>& tests) {
+  int passed = 0;
+  int failed = 0;
+  SOM_IN_TEST = true;
+  printf(\"running %zu tests\\n\", tests.size());
+  for (auto& test : tests) {
+    try {
+      test.body();
+      passed++;
+      printf(\"test %s ... ok\\n\", test.name.c_str());
+    } catch (SOM_TEST_FAILURE& failure) {
+      failed++;
+      printf(\"test %s ... FAILED\\n  %s\\n\", test.name.c_str(), failure.message.c_str());
+    }
+  }
+  printf(\"\\ntest result: %s. %d passed; %d failed\\n\", failed == 0 ? \"ok\" : \"FAILED\", passed, failed);
+  return failed == 0 ? 0 : 1;
+}
+            "
+            .to_string(),
+            name: "test".to_string(),
+            raw: None,
+            ext: true,
+        },
+    );
+}
+
 pub fn make_var_def(
     _scope: String,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
@@ -210,182 +943,207 @@ pub fn make_var_def(
     let type_: String;
     if definitions.get(&var_type).is_none() {
         type_ = "int".to_string();
-        println!(
-            "Warning: Unknown return type for variable {name}.",
-            name = &name
+        diagnostic::warn(
+            "Warning",
+            value.token.location.line,
+            value.token.location.col,
+            format!("Unknown return type for variable {}.", name),
         )
     } else {
         type_ = definitions[&var_type].name.clone();
     }
 
-    let mut base = vec![format!("std::unique_ptr<{}> {};", type_, name)];
+    let mut base = vec![format!("{} {};", type_, name)];
     base.push(gen(value, name, definitions));
 
     base.join("\n")
 }
-pub struct ExpSeg {
-    pub content: String,
-    pub id: String,
+thread_local! {
+    static ID_COUNT: Cell<usize> = const { Cell::new(0) };
+    static READABLE_IDS: Cell<bool> = const { Cell::new(false) };
+    static CHECKS: Cell<bool> = const { Cell::new(true) };
+    static BACKTRACES: Cell<bool> = const { Cell::new(true) };
+    static LINES_FROM: RefCell<Option<String>> = const { RefCell::new(None) };
 }
-fn gen_id() -> String {
-    let alphabet: [char; 16] = [
-        '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b', 'c', 'd', 'e', 'f',
-    ];
 
-    nanoid!(5, &alphabet[10..]).to_string() + &nanoid!(10, &alphabet).to_string()
-    //=> "4f90d13a42"
+// Stands for a #line directive back to the generated file itself, written
+// once the final line numbers are known by resolve_lines.
+const GENERATED_LINE: &str = "#line som_generated";
+
+// Precedes every statement with a #line directive pointing at `file`, the
+// source as given on the command line, so debuggers show the Something code.
+// None leaves them out.
+pub fn set_line_directives(file: Option<String>) {
+    LINES_FROM.with(|f| *f.borrow_mut() = file);
 }
-pub fn make_exp_seg(
-    exp_type: parse::Primitives,
-    size: String,
-    scope: String,
-    seg: parse::BinSeg,
-    last_id: String,
+
+// Replaces the directives back to the generated code with the lines they
+// are on, in the file that is compiled as `name`.
+pub fn resolve_lines(code: &str, name: &str) -> String {
+    code.lines()
+        .enumerate()
+        .map(|(i, line)| match line == GENERATED_LINE {
+            true => format!("#line {} {}", i + 2, cpp_string(name)),
+            false => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// A statement of the program, a function body or a test, mapped back to its
+// line in the source when building with --debug. Every C++ line counts as a
+// line of the source, so the statement is put on a single line around the
+// directives of the statements inside it.
+pub fn make_statement(
+    tok: parse::ParseTok,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
-) -> ExpSeg {
-    let mut decl_str: Vec<String> = vec![];
-    let id = scope.clone() + "_" + &gen_id();
-    if parse::prim_eq(&exp_type, &parse::Primitives::INT(32)) {
-        let left = gen(
-            DescriptorToken {
-                token: seg.left,
-                token_real_type: Some(exp_type.clone()),
-            },
-            "_".to_string(),
-            definitions,
-        );
-        if seg.right.is_some() {
-            let right = gen(
-                DescriptorToken {
-                    token: seg.right.unwrap(),
-                    token_real_type: Some(exp_type.clone()),
-                },
-                "_".to_string(),
-                definitions,
-            );
-            let line = format!("std::unique_ptr<INT{size}_LIT> {name}(new INT{size}_LIT(INT{size}_{:?}({left}, {right})));",seg.operation,name=id,size=size.clone(),left=left,right=right);
-            decl_str.push(line)
+) -> String {
+    let line = tok.location.line;
+    let code = gen(
+        DescriptorToken {
+            token: tok,
+            token_real_type: None,
+        },
+        "_".to_string(),
+        definitions,
+    );
+    let file = match LINES_FROM.with(|f| f.borrow().clone()) {
+        Some(file) if !code.is_empty() => file,
+        _ => return code,
+    };
+    let mut lines = vec![format!("#line {} {}", line, cpp_string(&file))];
+    let mut joined: Vec<&str> = vec![];
+    for part in code.lines() {
+        if part.trim_start().starts_with('#') {
+            if !joined.is_empty() {
+                lines.push(joined.join(" "));
+                joined.clear();
+            }
+            lines.push(part.to_string());
         } else {
-            let line = format!("std::unique_ptr<INT{size}_LIT> {name}(new INT{size}_LIT(INT{size}_{:?}({left}, *{right})));",seg.operation,name=id,size=size.clone(),left=left,right=last_id);
-            decl_str.push(line)
+            joined.push(part.trim());
         }
-    } else if parse::prim_eq(&exp_type, &parse::Primitives::SIGINT(32)) {
-        let left = gen(
-            DescriptorToken {
-                token: seg.left,
-                token_real_type: Some(exp_type.clone()),
-            },
-            "_".to_string(),
-            definitions,
-        );
-        if seg.right.is_some() {
-            let right = gen(
-                DescriptorToken {
-                    token: seg.right.unwrap(),
-                    token_real_type: Some(exp_type.clone()),
-                },
-                String::from("_"),
-                definitions,
+    }
+    if !joined.is_empty() {
+        lines.push(joined.join(" "));
+    }
+    lines.push(GENERATED_LINE.to_string());
+    lines.join("\n")
+}
+
+// Turns the shadow call stack on or off, it is kept in debug builds so panics
+// and crashes can show the Something functions that led to them.
+pub fn set_backtraces(enabled: bool) {
+    BACKTRACES.with(|b| b.set(enabled));
+}
+
+fn backtraces_enabled() -> bool {
+    BACKTRACES.with(|b| b.get())
+}
+
+// Turns the runtime checks of whole number arithmetic on or off, they are on
+// unless building with --unchecked.
+pub fn set_checks(enabled: bool) {
+    CHECKS.with(|c| c.set(enabled));
+}
+
+fn checks_enabled() -> bool {
+    CHECKS.with(|c| c.get())
+}
+
+// Restarts temporary naming, call once before generating a module so the same
+// source always produces the same C++. Readable names describe what each
+// temporary holds instead of only numbering it.
+pub fn reset_ids(readable: bool) {
+    ID_COUNT.with(|count| count.set(0));
+    READABLE_IDS.with(|r| r.set(readable));
+}
+
+// Something identifiers cannot start with an underscore so these names never
+// collide with user variables.
+fn gen_id(hint: &str) -> String {
+    let id = ID_COUNT.with(|count| {
+        let id = count.get();
+        count.set(id + 1);
+        id
+    });
+    if READABLE_IDS.with(|r| r.get()) {
+        format!("_{}_{}", hint, id)
+    } else {
+        format!("_t{}", id)
+    }
+}
+
+// The C++ expression computing an expression tree, with the declarations its
+// operands need. Helpers like INT32_PLUS take the operand values themselves.
+fn exp_value(
+    tok: parse::ParseTok,
+    exp_type: &parse::Primitives,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> (Vec<String>, String) {
+    match tok.tok_type {
+        parse::ParseType::EXP => {
+            let seg = *tok.expression.unwrap().body;
+            let (mut decls, left) = exp_value(seg.left, exp_type, definitions);
+            let (mut right_decls, right) = exp_value(seg.right, exp_type, definitions);
+            decls.append(&mut right_decls);
+            let variant = prim_var_str(exp_type.clone());
+            let at = match variant[0] == "INT" && checks_enabled() {
+                true => format!(", \"{}:{}\"", tok.location.line, tok.location.col),
+                false => String::new(),
+            };
+            let value = format!(
+                "{TYPE}{size}_{:?}({left}, {right}{at})",
+                seg.operation,
+                TYPE = variant[0],
+                size = variant[1],
+                left = left,
+                right = right,
+                at = at
             );
-            let line = format!("std::unique_ptr<SIGINT{size}_LIT> {name}(new SIGINT{size}_LIT(SIGINT{size}_{:?}({left}, {right})));",seg.operation,name=id,size=size.clone(),left=left,right=right);
-            decl_str.push(line)
-        } else {
-            let line = format!("std::unique_ptr<SIGINT{size}_LIT> {name}(new SIGINT{size}_LIT(SIGINT{size}_{:?}({left}, *{right})));",seg.operation,name=id,size=size.clone(),left=left,right=last_id);
-            decl_str.push(line)
+            (decls, value)
         }
-    } else if parse::prim_eq(&exp_type, &parse::Primitives::FLOAT(32)) {
-        let left = gen(
-            DescriptorToken {
-                token: seg.left,
-                token_real_type: Some(exp_type.clone()),
-            },
-            String::from("_"),
-            definitions,
-        );
-        if seg.right.is_some() {
-            let right = gen(
+        parse::ParseType::NUMBER => {
+            let value = make_number(
                 DescriptorToken {
-                    token: seg.right.unwrap(),
-                    token_real_type: Some(exp_type),
+                    token_real_type: Some(exp_type.clone()),
+                    token: tok,
                 },
                 String::from("_"),
                 definitions,
             );
-            let line = format!("std::unique_ptr<FLOAT{size}_LIT> {name}(new FLOAT{size}_LIT(FLOAT{size}_{:?}({left}, {right})));",seg.operation,name=id,size=size.clone(),left=left,right=right);
-            decl_str.push(line)
-        } else {
-            let line = format!("std::unique_ptr<FLOAT{size}_LIT> {name}(new FLOAT{size}_LIT(FLOAT{size}_{:?}({left}, *{right})));",seg.operation,name=id,size=size.clone(),left=left,right=last_id);
-            decl_str.push(line)
+            (vec![], value)
         }
+        _ => make_value(tok, definitions),
     }
-    return ExpSeg {
-        content: decl_str.join(""),
-        id,
-    };
 }
+
 pub fn make_exp(
     scope: String,
     parent: DescriptorToken,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
 ) -> String {
     let body = &parent.token;
-
-    let mut exp_type = body.clone().expression.unwrap().exp_type;
-    if parent.token_real_type.is_some() {
-        exp_type = parent.token_real_type.unwrap();
-    }
+    let exp_type = parent
+        .token_real_type
+        .clone()
+        .unwrap_or_else(|| body.clone().expression.unwrap().exp_type);
 
     let exp_type_size = prim_var_str(exp_type.clone());
-    let size = &exp_type_size[1];
-
     if exp_type_size.len() != 2 {
-        println!(
-            "Unknown type operation ({line}:{col}): {}",
+        diagnostic::fatal(
+            "Unknown type operation",
+            body.location.line,
+            body.location.col,
             scope,
-            line = body.location.line,
-            col = body.location.start_col
-        );
-        std::process::exit(1);
-    }
-    let mut decl_strs: Vec<String> = vec![];
-    let mut symbols: Vec<String> = vec![];
-    for seg in body.clone().expression.unwrap().body {
-        let seg = make_exp_seg(
-            exp_type.clone(),
-            size.to_string(),
-            scope.clone().replace(".", "_"),
-            seg,
-            symbols.last().unwrap_or(&"".to_string()).to_string(),
-            definitions,
         );
-        decl_strs.push(seg.content);
-        symbols.push(seg.id);
     }
-
-    if parse::prim_eq(&exp_type, &parse::Primitives::INT(32)) {
-        let line = format!(
-            "{name}= std::make_unique<INT{size}_LIT>(*{v});",
-            name = scope,
-            size = size.clone(),
-            v = symbols.last().unwrap().to_string(),
-        );
-        decl_strs.push(line)
-    } else if parse::prim_eq(&exp_type, &parse::Primitives::SIGINT(32)) {
-        let line = format!(
-            "{name}= std::make_unique<SIGINT{size}_LIT>(*{v});",
-            name = scope,
-            size = size.clone(),
-            v = symbols.last().unwrap().to_string(),
-        );
-        decl_strs.push(line)
-    } else if parse::prim_eq(&exp_type, &parse::Primitives::FLOAT(32)) {
-        let line = format!(
-            "{name}= std::make_unique<FLOAT{size}_LIT>(*{v});",
-            name = scope,
-            size = size.clone(),
-            v = symbols.last().unwrap().to_string(),
-        );
-        decl_strs.push(line)
+    init_lib(definitions, exp_type.clone());
+    let (mut decl_strs, value) = exp_value(parent.token, &exp_type, definitions);
+    if scope == "_" {
+        decl_strs.push(format!("{};", value));
+    } else {
+        decl_strs.push(format!("{name} = {v};", name = scope, v = value));
     }
     decl_strs.join("\n")
 }
@@ -425,7 +1183,7 @@ pub fn make_number(
             &parse::Primitives::INT(32),
         ) {
             format!(
-                "{scope} = std::make_unique<{TYPE}{size}_LIT>({TYPE}{size}_LIT({v}));",
+                "{scope} = {TYPE}{size}_LIT({v});",
                 TYPE = type_[0],
                 size = type_[1],
                 scope = scope,
@@ -433,7 +1191,7 @@ pub fn make_number(
             )
         } else {
             format!(
-                "{scope} = std::make_unique<{TYPE}{size}_LIT>({TYPE}{size}_LIT({v}));",
+                "{scope} = {TYPE}{size}_LIT({v});",
                 TYPE = type_[0],
                 size = type_[1],
                 scope = scope,
@@ -443,62 +1201,353 @@ pub fn make_number(
     }
 }
 
+// A C++ expression for a decoded string value. Control characters are written
+// as octal escapes, which unlike \x cannot swallow the digits that follow.
+pub fn cpp_string(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            '?' => literal.push_str("\\?"), // no trigraphs
+            ch if ch.is_control() && (ch as u32) < 0x80 => {
+                literal.push_str(&format!("\\{:03o}", ch as u32))
+            }
+            ch => literal.push(ch),
+        }
+    }
+    literal.push('"');
+    if value.contains('\0') {
+        // A plain literal would end at the first NUL.
+        format!("std::string({}, {})", literal, value.len())
+    } else {
+        literal
+    }
+}
+
+// Runs declarations inside a lambda returning `value`, so code that needs
+// temporaries can still be used where C++ expects a single expression.
+fn in_lambda(mut decls: Vec<String>, value: String) -> String {
+    if decls.is_empty() {
+        return value;
+    }
+    let mut lines = vec![String::from("[&]() {")];
+    lines.append(&mut decls);
+    lines.push(format!("return {};", value));
+    lines.push(String::from("}()"));
+    lines.join("\n")
+}
+
+// Joins the pieces of an interpolated string.
+fn make_template(
+    template: Vec<parse::ParseTok>,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> String {
+    let mut decls = vec![];
+    let mut pieces = vec![];
+    for part in template {
+        if part.tok_type == parse::ParseType::STRING {
+            pieces.push(cpp_string(&part.string.unwrap().content));
+            continue;
+        }
+        let (mut part_decls, value) = make_value(part, definitions);
+        decls.append(&mut part_decls);
+        pieces.push(format!("{}.display()", value));
+    }
+    in_lambda(decls, format!("std::string() + {}", pieces.join(" + ")))
+}
+
 pub fn make_string(
     tok: DescriptorToken,
     scope_name: String,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
 ) -> String {
     init_str_lit(definitions);
+    let string = tok.token.string.unwrap();
+    let v = if string.template.is_empty() {
+        cpp_string(&string.content)
+    } else {
+        make_template(string.template, definitions)
+    };
     if scope_name == "_" {
-        format!(
-            "STR_LIT(\"{v}\")",
-            v = tok.token.string.unwrap().clone().content
-        )
+        format!("STR_LIT({v})", v = v)
     } else {
-        format!(
-            "{name} = std::make_unique<STR_LIT>(STR_LIT(\"{v}\"));",
-            v = tok.token.string.unwrap().clone().content,
-            name = scope_name
-        )
+        format!("{name} = STR_LIT({v});", v = v, name = scope_name)
+    }
+}
+
+// Evaluates a value into a temporary, returning its declarations and a C++
+// expression for the value itself.
+fn make_value(
+    arg: parse::ParseTok,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> (Vec<String>, String) {
+    if arg.tok_type == parse::ParseType::LABEL {
+        let lit = make_ident(
+            DescriptorToken {
+                token_real_type: None,
+                token: arg,
+            },
+            Some("_".to_string()),
+            definitions,
+        );
+        return (vec![], lit);
+    }
+    if arg.tok_type == parse::ParseType::FNCALL && arg.fncall.clone().unwrap().ret_type.is_none() {
+        diagnostic::fatal(
+            "Bad type",
+            arg.location.line,
+            arg.location.col,
+            format!(
+                "Function {} does not return a value",
+                arg.fncall.clone().unwrap().name
+            ),
+        );
+    }
+    let type_ = parse::get_prim(arg.clone());
+    init_lib(definitions, type_.clone());
+    let tmp = gen_id("value");
+    let decls = vec![
+        format!("{} {};", definitions.get(&type_).unwrap().name, tmp),
+        gen(
+            DescriptorToken {
+                token_real_type: None,
+                token: arg,
+            },
+            tmp.clone(),
+            definitions,
+        ),
+    ];
+    (decls, tmp)
+}
+
+fn make_assert(
+    tok: DescriptorToken,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> String {
+    init_fn_test(definitions);
+    let call = tok.token.fncall.clone().unwrap();
+    let line = tok.token.location.line;
+    let col = tok.token.location.col;
+    // The arguments were checked against the signature in som_std when parsed.
+    let location = format!("{}:{}", line, col);
+    let mut decls = vec![];
+    let mut values = vec![];
+    for arg in call.args.clone() {
+        let (mut arg_decls, value) = make_value(arg, definitions);
+        decls.append(&mut arg_decls);
+        values.push(value);
+    }
+    if call.name == "assert" {
+        decls.push(format!(
+            "if ({v}.num == 0) {{ som_assert_fail(\"assertion failed ({location})\"); }}",
+            v = values[0],
+            location = location
+        ));
+    } else {
+        decls.push(format!(
+            "if ({left}.display() != {right}.display()) {{ som_assert_fail(std::string(\"assertion failed ({location}): left == right (left: \") + {left}.display() + \", right: \" + {right}.display() + \")\"); }}",
+            left = values[0],
+            right = values[1],
+            location = location
+        ));
+    }
+    decls.join("\n")
+}
+
+pub fn make_test(
+    tok: DescriptorToken,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> String {
+    init_fn_test(definitions);
+    let test = tok.token.test.unwrap();
+    let mut body: Vec<String> = vec![];
+    for line in test.body {
+        body.push(make_statement(line, definitions));
+    }
+    format!(
+        "som_tests.push_back(SOM_TEST{{{name}, [&]() {{\n{body}\n}}}});",
+        name = cpp_string(&test.name),
+        body = body.join("\n")
+    )
+}
+
+// Calls a std function that gives back a value, they are defined in som_std.cc
+// with a som_ prefix.
+fn make_std_value(
+    tok: DescriptorToken,
+    scope_name: String,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> String {
+    let call = tok.token.fncall.unwrap();
+    match call.name.as_str() {
+        "read_line" | "read_all" | "read_file" | "write_file" | "append_file" | "io_error" => {
+            init_fn_files(definitions)
+        }
+        "arg" | "arg_count" | "env" | "exit" => init_fn_process(definitions),
+        "panic" => init_fn_panic(definitions),
+        "abs" | "min" | "max" | "clamp" | "pow" | "sqrt" | "floor" | "ceil" | "round" | "sin"
+        | "cos" | "tan" | "asin" | "acos" | "atan" | "atan2" | "exp" | "ln" | "log10" | "pi"
+        | "e" => init_fn_math_module(definitions),
+        _ => init_fn_strings(definitions),
+    }
+    let math = matches!(
+        call.name.as_str(),
+        "sqrt"
+            | "floor"
+            | "ceil"
+            | "round"
+            | "sin"
+            | "cos"
+            | "tan"
+            | "asin"
+            | "acos"
+            | "atan"
+            | "atan2"
+            | "exp"
+            | "ln"
+            | "log10"
+    );
+    if math && call.ret_type == Some(parse::Primitives::FLOAT(64)) {
+        init_fn_math64(definitions);
+    }
+    // Whole numbers only, abs of a float always fits.
+    let checked_abs = match call.args.first().map(|arg| parse::get_prim(arg.clone())) {
+        Some(arg_type @ parse::Primitives::INT(_)) if call.name == "abs" && checks_enabled() => {
+            init_fn_abs(definitions, prim_var_str(arg_type)[1].clone());
+            true
+        }
+        _ => false,
+    };
+    let mut decls = vec![];
+    let mut values = vec![];
+    for arg in call.args {
+        let (mut arg_decls, value) = make_value(arg, definitions);
+        decls.append(&mut arg_decls);
+        values.push(value);
+    }
+    if call.name == "join" {
+        let pieces = values.split_off(1);
+        values.push(format!("{{{}}}", pieces.join(", ")));
+    }
+    if call.name == "panic" {
+        values[0] = format!("{}.text()", values[0]);
+    }
+    // Calls that can panic take their location.
+    let panics = matches!(call.name.as_str(), "panic" | "parse_int" | "parse_float");
+    if panics || (call.name == "pow" && checks_enabled()) || checked_abs {
+        values.push(format!(
+            "\"{}:{}\"",
+            tok.token.location.line, tok.token.location.col
+        ));
+    }
+    let value = format!("som_{}({})", call.name, values.join(", "));
+    if scope_name == "_" {
+        // Called for what it does, like write_file! path, text;
+        decls.push(format!("{};", value));
+        decls.join("\n")
+    } else {
+        decls.push(format!("{name} = {v};", name = scope_name, v = value));
+        decls.join("\n")
     }
 }
 
+// Converts a value with `as`. Whole numbers grow and become floats through
+// the constructor, anything that might not fit is checked first.
+fn make_cast(
+    tok: DescriptorToken,
+    scope_name: String,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> String {
+    let call = tok.token.fncall.unwrap();
+    let location = format!("\"{}:{}\"", tok.token.location.line, tok.token.location.col);
+    let arg = call.args[0].clone();
+    let from = parse::get_prim(arg.clone());
+    let to = call.ret_type.unwrap();
+    init_lib(definitions, to.clone());
+    let (mut decls, v) = make_value(arg, definitions);
+    let name = definitions.get(&to).unwrap().name.clone();
+    let value = match (&from, &to) {
+        _ if from == to => v,
+        (_, parse::Primitives::STRING) => format!("STR_LIT({}.display())", v),
+        (parse::Primitives::STRING, parse::Primitives::INT(size)) => {
+            init_fn_conversions(definitions);
+            format!(
+                "{name}(som_str_to_whole({v}.text(), INT{size}_MIN, INT{size}_MAX, \"i{size}\", {location}))",
+                name = name,
+                v = v,
+                size = size,
+                location = location
+            )
+        }
+        (parse::Primitives::STRING, parse::Primitives::FLOAT(size)) => {
+            init_fn_conversions(definitions);
+            format!(
+                "{name}(som_str_to_float({v}.text(), \"f{size}\", {location}))",
+                name = name,
+                v = v,
+                size = size,
+                location = location
+            )
+        }
+        (_, parse::Primitives::INT(size)) if !parse::widens(&from, &to) => {
+            init_fn_conversions(definitions);
+            format!(
+                "{name}(som_to_whole((long double){v}.num, INT{size}_MIN, INT{size}_MAX, \"i{size}\", {v}.display(), {location}))",
+                name = name,
+                v = v,
+                size = size,
+                location = location
+            )
+        }
+        // Growing, and f64 to f32 which rounds like C.
+        _ => format!("{}({}.num)", name, v),
+    };
+    if scope_name == "_" {
+        decls.push(format!("{};", value));
+    } else {
+        decls.push(format!("{scope} = {v};", scope = scope_name, v = value));
+    }
+    decls.join("\n")
+}
+
 fn make_std_fncall(
     tok: DescriptorToken,
     parent_scope: Option<String>,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
 ) -> String {
+    let fn_name = tok.token.fncall.clone().unwrap().name;
+    if fn_name == "as" {
+        return make_cast(tok, parent_scope.unwrap(), definitions);
+    }
+    if fn_name == "assert" || fn_name == "assert_eq" {
+        return make_assert(tok, definitions);
+    }
+    if tok.token.fncall.clone().unwrap().ret_type.is_some()
+        || fn_name == "exit"
+        || fn_name == "panic"
+    {
+        return make_std_value(tok, parent_scope.unwrap(), definitions);
+    }
     init_str_lit(definitions);
     let mut arg_decls: Vec<String> = vec![];
-    let mut arg_types: Vec<String> = vec![];
-    let scope: String = gen_id();
+    let scope: String = gen_id(&(tok.token.fncall.clone().unwrap().name + "_args"));
 
-    // std::vector<std::unique_ptr<STR_LIT>>
     for arg in tok.token.fncall.clone().unwrap().args {
-        if tok.token.fncall.clone().unwrap().name == "print" {
-            if arg.tok_type == parse::ParseType::STRING {
-                let lit = make_string(
+        if fn_name == "print" || fn_name == "eprint" {
+            // Strings are pushed as they are, sharing their buffer.
+            let value = if arg.tok_type == parse::ParseType::STRING {
+                make_string(
                     DescriptorToken {
                         token_real_type: None,
                         token: arg,
                     },
                     String::from("_"),
                     definitions,
-                );
-                arg_types.push(
-                    definitions
-                        .get(&parse::Primitives::STRING)
-                        .unwrap()
-                        .name
-                        .clone(),
-                );
-                let arg_lit = format!(
-                    "std::unique_ptr<STR_LIT> {name}(new {lit});\n{scope}.push_back(std::move({name}));",
-                    name = scope.clone() + "_" + &gen_id(),
-                    lit = lit,
-                    scope = scope
-                );
-                arg_decls.push(arg_lit);
+                )
             } else if arg.tok_type == parse::ParseType::NUMBER {
                 let lit = make_number(
                     DescriptorToken {
@@ -508,78 +1557,37 @@ fn make_std_fncall(
                     "_".to_string(),
                     definitions,
                 );
-
-                let arg_lit = format!(
-                    "std::unique_ptr<STR_LIT> {name}(new STR_LIT({lit}.display()));\n{scope}.push_back(std::move({name}));",
-                    name = scope.clone() + "_" + &gen_id(),
-                    lit = lit,
-                    scope = scope
-                );
-                arg_decls.push(arg_lit);
-            } else if arg.tok_type == parse::ParseType::LABEL {
-                let lit = make_ident(
-                    DescriptorToken {
-                        token_real_type: None,
-                        token: arg,
-                    },
-                    parent_scope.clone(),
-                    definitions,
-                );
-                let arg_lit = format!(
-                    "std::unique_ptr<STR_LIT> {name}(new STR_LIT({lit}.display()));\n{scope}.push_back(std::move({name}));",
-                    name = scope.clone() + "_" + &gen_id(),
-                    lit = lit,
-                    scope = scope
-                );
-                arg_decls.push(arg_lit);
-            } else if arg.tok_type == parse::ParseType::EXP {
-                let sub_var = scope.clone() + "_" + &gen_id();
-                let type_ = arg.expression.clone().unwrap().exp_type;
-                init_lib(definitions, type_.clone());
-
-                let str_type = definitions.get(&type_).unwrap().name.clone();
-                let mut base = vec![format!("std::unique_ptr<{}> {};", str_type, sub_var)];
-
-                let lit = make_exp(
-                    sub_var.clone(),
-                    DescriptorToken {
-                        token_real_type: None,
-                        token: arg,
-                    },
-                    definitions,
-                );
-                base.push(lit);
-
-                let arg_lit = format!(
-                    "std::unique_ptr<STR_LIT> {name}(new STR_LIT({v}.display()));\n{scope}.push_back(std::move({name}));",
-                    name=sub_var.clone()+"_"+&gen_id(),
-                    scope = scope,
-                    v = format!("(*{name})", name = &sub_var)
-                );
-                arg_decls.append(&mut base);
-                arg_decls.push(arg_lit);
-            }
+                format!("STR_LIT({}.display())", lit)
+            } else {
+                let is_string = parse::get_prim(arg.clone()) == parse::Primitives::STRING;
+                let (mut decls, value) = make_value(arg, definitions);
+                arg_decls.append(&mut decls);
+                match is_string {
+                    true => value,
+                    false => format!("STR_LIT({}.display())", value),
+                }
+            };
+            arg_decls.push(format!("{}.push_back({});", scope, value));
         } else if tok.token.fncall.clone().unwrap().name == "include" {
             if arg.tok_type == parse::ParseType::STRING {
                 init_fn_include(definitions, arg.string.unwrap().content)
             } else {
-                println!(
-                        "Bad type({line},{col}): Include takes argument of type STRING instead got type {:?}",
-                        arg.tok_type,
-                        line = arg.location.line,
-                        col = arg.location.start_col
-                    );
-                std::process::exit(1);
+                diagnostic::fatal(
+                    "Bad type",
+                    arg.location.line,
+                    arg.location.col,
+                    format!(
+                        "Include takes argument of type STRING instead got type {:?}",
+                        arg.tok_type
+                    ),
+                );
             }
         }
     }
 
-    if tok.token.fncall.clone().unwrap().name == "print" {
+    if fn_name == "print" || fn_name == "eprint" {
         init_fn_io(definitions);
-        let arg_lit = format!(
-            "std::vector<std::unique_ptr<STR_LIT>> {name};",
-            name = scope.clone(),
-        );
+        let arg_lit = format!("std::vector<STR_LIT> {name};", name = scope.clone());
         arg_decls.insert(0, arg_lit);
         let print_call = format!(
             "{fnName}(&{name});",
@@ -599,163 +1607,152 @@ fn make_ident(
 ) -> String {
     let lit = tok.token.ident.unwrap();
     init_lib(definitions, lit.var_type);
+    // Reads copy the value, strings only copy a pointer to their buffer.
     if scope.clone().unwrap() == "_" {
-        format!("(*{name})", name = lit.name)
+        lit.name
     } else {
-        format!(
-            "{scope} = std::move({name});",
-            name = lit.name,
-            scope = scope.unwrap()
-        )
+        format!("{scope} = {name};", name = lit.name, scope = scope.unwrap())
+    }
+}
+
+// The C++ name of a function. Prefixed so functions named like C++ keywords,
+// libc functions or main do not clash with them.
+fn fn_ident(name: &str) -> String {
+    format!("som_fn_{}", name)
+}
+
+// Runs a user function main after the top level statements, its result becomes
+// the exit status of the program.
+pub fn make_entry(func: &parse::ParseTok) -> String {
+    let def = func.fnmake.clone().unwrap();
+    frame_call(
+        format!("return {}().num;", fn_ident(&def.name)),
+        &def.name,
+        None,
+    )
+}
+
+// Runs a statement calling a function inside a frame of the shadow call stack
+// when there is one. No location is main run by the program.
+fn frame_call(call: String, name: &str, location: Option<&parse::ParseLoc>) -> String {
+    if !backtraces_enabled() {
+        return call;
     }
+    let location = match location {
+        Some(loc) => format!("\"{}:{}\"", loc.line, loc.col),
+        None => String::from("nullptr"),
+    };
+    format!(
+        "{{\nSOM_CALL som_frame(\"{name}\", {location});\n{call}\n}}",
+        name = name,
+        location = location,
+        call = call
+    )
 }
 
+// The C++ expression passing a value to a function or returning it, with the
+// declarations it needs. Variables and literals are passed as they are.
+fn call_arg(
+    arg: parse::ParseTok,
+    definitions: &mut IndexMap<parse::Primitives, PrimType>,
+) -> (Vec<String>, String) {
+    match arg.tok_type {
+        parse::ParseType::NUMBER | parse::ParseType::STRING => {
+            let value = gen(
+                DescriptorToken {
+                    token_real_type: None,
+                    token: arg,
+                },
+                String::from("_"),
+                definitions,
+            );
+            (vec![], value)
+        }
+        _ => make_value(arg, definitions),
+    }
+}
+
+// Functions become plain C++ functions taking and returning values, so the
+// C++ compiler can inline them.
 fn make_func(
     tok: DescriptorToken,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
 ) -> String {
-    if definitions
-        .get(&tok.token.clone().fnmake.unwrap().return_type)
-        .is_none()
-    {
-        init_lib(definitions, tok.token.clone().fnmake.unwrap().return_type)
+    if backtraces_enabled() {
+        init_fn_backtrace(definitions);
     }
-    let mut param_decls = vec![];
-    for param in tok.token.clone().fnmake.unwrap().params {
-        if definitions.get(&param.value_type).is_none() {
-            init_lib(definitions, param.clone().value_type)
-        }
-        let type_name = &definitions.get(&param.value_type).unwrap().clone().name;
-        let param_prop = format!(
-            "std::unique_ptr<{TYPE}> {name};\n",
-            TYPE = type_name,
-            name = param.name
-        );
-        param_decls.push(param_prop);
+    let func = tok.token.fnmake.clone().unwrap();
+    init_lib(definitions, func.return_type.clone());
+    let mut params = vec![];
+    for param in &func.params {
+        init_lib(definitions, param.value_type.clone());
+        let type_name = &definitions.get(&param.value_type).unwrap().name;
+        params.push(format!("{} {}", type_name, param.name));
     }
-    let mut body: Vec<String> = vec![];
-    let local_scope = &mut *definitions;
-    local_scope.insert(
-        parse::Primitives::INSCOPE(tok.token.fnmake.clone().unwrap().name),
+    definitions.insert(
+        parse::Primitives::INSCOPE(func.name.clone()),
         PrimType {
             def: String::from(""),
-            name: tok.token.fnmake.clone().unwrap().name,
+            name: func.name.clone(),
             raw: Some(tok.token.clone()),
             ext: false,
         },
     );
-    for line in tok.token.clone().fnmake.unwrap().body {
-        let statement = gen(
-            DescriptorToken {
-                token: line,
-                token_real_type: None,
-            },
-            "_".to_string(),
-            local_scope,
-        );
-        body.push(statement);
+    // Each function numbers its temporaries from zero so editing one function does
+    // not rename the temporaries of every function after it.
+    let outer_count = ID_COUNT.with(|count| count.replace(0));
+    let mut body: Vec<String> = vec![];
+    for line in func.body.clone() {
+        body.push(make_statement(line, definitions));
     }
-    let func = format!(
+    ID_COUNT.with(|count| count.set(outer_count));
+    let ret_type = definitions.get(&func.return_type).unwrap().name.clone();
+    // Without a ret the function gives back the empty value of its type.
+    if func.body.last().map(|line| &line.tok_type) != Some(&parse::ParseType::FNRETURN) {
+        body.push(format!("return {}();", ret_type));
+    }
+    let def = format!(
         "
-    struct {name} {{
-        std::unique_ptr<{ret_type}> RETURN;
-        {params}
-        void body() {{
-            {body}
-        }}
-        int call() {{
-            body();
-            return 0;
-        }}
-    }};
-        ",
-        name = tok.token.fnmake.clone().unwrap().name,
-        ret_type = definitions
-            .get(&tok.token.fnmake.clone().unwrap().return_type)
-            .unwrap()
-            .name,
-        params = param_decls.join(""),
+{ret_type} {name}({params}) {{
+{body}
+}}
+",
+        ret_type = ret_type,
+        name = fn_ident(&func.name),
+        params = params.join(", "),
         body = body.join("\n"),
     );
     definitions.insert(
-        parse::Primitives::INSCOPE(tok.token.fnmake.clone().unwrap().name),
+        parse::Primitives::INSCOPE(func.name.clone()),
         PrimType {
-            def: func,
-            name: tok.token.fnmake.clone().unwrap().name,
+            def,
+            name: func.name,
             raw: Some(tok.token),
             ext: false,
         },
     );
     "".to_string()
 }
+
 pub fn make_fncall(
     tok: DescriptorToken,
     scope_name: Option<String>,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
 ) -> String {
-    let id = gen_id();
-    let name = tok.token.fncall.clone().unwrap().name;
-    let mut params = vec![];
-    let def = &mut definitions
-        .get(&parse::Primitives::INSCOPE(name.clone().to_string()))
-        .unwrap()
-        .raw
-        .clone();
-    let arg_list = &def.clone().unwrap().fnmake.unwrap().params;
-    for (i, sup_arg) in tok.token.fncall.unwrap().args.iter().enumerate() {
-        let arg = &arg_list[i];
-        let name = &arg.name;
-        let mut sc = scope_name.to_owned();
-
-        if sc.clone().unwrap() == "_"
-            || sup_arg.tok_type == parse::ParseType::EXP
-            || sup_arg.tok_type == parse::ParseType::FNCALL
-        {
-            sc = Some(format!("{id}.{name}", id = id, name = name));
-        } else {
-            sc = Some("_".to_string())
-        }
-        let val = gen(
-            DescriptorToken {
-                token_real_type: None,
-                token: sup_arg.clone(),
-            },
-            sc.clone().unwrap(),
-            definitions,
-        );
-        let type_str = definitions.get(&arg.value_type).unwrap().name.clone();
-        init_lib(definitions, arg.clone().value_type);
-        if scope_name.clone().unwrap() != "_"
-            && sup_arg.tok_type != parse::ParseType::EXP
-            && sup_arg.tok_type != parse::ParseType::FNCALL
-        {
-            params.push(format!(
-                "{id}.{name} = std::make_unique<{TYPE}>({val});",
-                id = id,
-                TYPE = type_str,
-                val = val,
-                name = name
-            ))
-        } else {
-            params.push(val)
-        }
-    }
-    let mut decls: Vec<String> = vec![];
-    let base = format!(
-        "{name} {id};
-        {args}\n{id}.call();",
-        name = name,
-        id = id,
-        args = params.join("\n")
-    );
-    decls.push(base.clone());
-    if scope_name.is_some() && scope_name.clone().unwrap() != "_" {
-        decls.push(format!(
-            "{name} = std::move({id}.RETURN);",
-            name = scope_name.unwrap(),
-            id = id
-        ));
+    let call = tok.token.fncall.clone().unwrap();
+    let mut decls = vec![];
+    let mut args = vec![];
+    for arg in call.args {
+        let (mut arg_decls, value) = call_arg(arg, definitions);
+        decls.append(&mut arg_decls);
+        args.push(value);
     }
+    let value = format!("{}({})", fn_ident(&call.name), args.join(", "));
+    let statement = match scope_name {
+        Some(scope) if scope != "_" => format!("{} = {};", scope, value),
+        _ => format!("{};", value),
+    };
+    decls.push(frame_call(statement, &call.name, Some(&tok.token.location)));
     decls.join("\n")
 }
 
@@ -763,16 +1760,10 @@ fn make_return(
     tok: DescriptorToken,
     definitions: &mut IndexMap<parse::Primitives, PrimType>,
 ) -> String {
-    let gen_val = gen(
-        DescriptorToken {
-            token_real_type: None,
-            token: tok.token.fnreturn.unwrap().value.unwrap(),
-        },
-        String::from("RETURN"),
-        definitions,
-    );
-
-    gen_val
+    let value = tok.token.fnreturn.unwrap().value.unwrap();
+    let (mut decls, value) = call_arg(value, definitions);
+    decls.push(format!("return {};", value));
+    decls.join("\n")
 }
 pub fn gen(
     tok: DescriptorToken,
@@ -804,6 +1795,8 @@ pub fn gen(
         make_func(tok, definitions)
     } else if tok.token.tok_type == parse::ParseType::FNRETURN {
         make_return(tok, definitions)
+    } else if tok.token.tok_type == parse::ParseType::TEST {
+        make_test(tok, definitions)
     } else {
         println!("{:#?}", tok.token);
         unimplemented!()
@@ -1,6 +1,10 @@
-use termion::color;
+use std::fmt;
+pub mod analysis;
+pub mod diagnostic;
+pub mod format;
 pub mod generation;
 pub mod parse;
+pub mod pretty;
 mod som_std;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -23,9 +27,24 @@ pub enum TokenType {
     COMMA,
 
     PLUSBIN,
+    CONCAT,
     SUBBIN,
     MULBIN,
     DIVBIN,
+    MODBIN,
+    POWBIN,
+    // bitwise
+    BITAND,
+    BITOR,
+    BITXOR,
+    BITNOT,
+    SHL,
+    SHR,
+    // prefix, NEG is a - the parser found in front of an operand
+    NEG,
+    NOT,
+    // postfix, x as f32
+    AS,
     //comparison
     GCMP,
     GECMP,
@@ -38,47 +57,308 @@ pub enum TokenType {
     OR,
 }
 
-#[derive(Clone, Debug)]
+// Where a token or piece of trivia sits in the source. Ranges are half open,
+// `col` counts characters from 1 on `line`.
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct LexTokenLoc {
-    line_start: usize,
-    line: u32,
-    col: usize,
-    end_col: usize,
+    pub line: u32,
+    pub col: usize,
+    // Character offsets from the start of the file.
+    pub start: usize,
+    pub end: usize,
+    // Byte offsets from the start of the file.
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+// Source text the parser skips over, attached to the token that follows it.
+// Together with the token contents it reproduces the input exactly.
+#[derive(Clone, Debug)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub loc: LexTokenLoc,
 }
 
 #[derive(Clone, Debug)]
 pub struct LexToken {
     tok_type: TokenType,
+    trivia: Vec<Trivia>,
     content: String,
     loc: LexTokenLoc,
 }
 
-#[derive(Clone, Debug)]
-pub struct TokenLoc {
-    line_start: usize,
-    line: u32,
-    col: usize,
+impl LexToken {
+    pub fn tok_type(&self) -> &TokenType {
+        &self.tok_type
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn loc(&self) -> &LexTokenLoc {
+        &self.loc
+    }
+
+    // Character offset of the token from the start of the file.
+    pub fn start(&self) -> usize {
+        self.loc.start
+    }
+
+    pub fn line(&self) -> u32 {
+        self.loc.line
+    }
+
+    // Line of the last character, later than `line` for multi-line strings.
+    pub fn end_line(&self) -> u32 {
+        self.loc.line + self.content.matches('\n').count() as u32
+    }
+
+    // Whitespace and comments between the previous token and this one.
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
+    }
+}
+
+impl fmt::Display for LexToken {
+    // line:col followed by the byte range.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} {}..{} {:?} {:?}",
+            self.loc.line,
+            self.loc.col,
+            self.loc.byte_start,
+            self.loc.byte_end,
+            self.tok_type,
+            self.content
+        )
+    }
+}
+
+// A piece of a string literal, text with its escapes decoded or the source of
+// an interpolated expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    // Character index of the expression in the literal and its source.
+    Code(usize, String),
+}
+
+// Splits a string literal as written in the source, quotes included, into
+// text and interpolated expressions. Errors carry the character index of the
+// problem in `raw` and the kind of diagnostic.
+//
+//   "a\tb"        \n \t \r \0 \\ \" \{ \} and \u{1F600}, a \ before a line break
+//                 skips it and the indentation that follows
+//   "hi {name}"   the value of name, any expression without strings or braces
+//   r"C:\dir"     raw strings keep every character, r#"say "hi""# may hold quotes
+fn split_string(raw: &str) -> Result<Vec<StringPart>, (usize, &'static str, String)> {
+    let chars: Vec<char> = raw.chars().collect();
+    if chars[0] == 'r' {
+        let hashes = chars[1..].iter().take_while(|ch| **ch == '#').count();
+        let text = chars[hashes + 2..chars.len() - hashes - 1].iter().collect();
+        return Ok(vec![StringPart::Text(text)]);
+    }
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut i = 1;
+    while i < chars.len() - 1 {
+        if chars[i] == '{' {
+            let close = match chars[i + 1..chars.len() - 1]
+                .iter()
+                .position(|ch| *ch == '}')
+            {
+                Some(close) => close,
+                None => {
+                    return Err((
+                        i,
+                        "Bad interpolation",
+                        String::from("Expected a } to end the interpolation"),
+                    ))
+                }
+            };
+            let code: String = chars[i + 1..i + 1 + close].iter().collect();
+            if code.contains('{') {
+                return Err((
+                    i,
+                    "Bad interpolation",
+                    String::from("Interpolations cannot be nested"),
+                ));
+            }
+            if code.trim().is_empty() {
+                return Err((
+                    i,
+                    "Bad interpolation",
+                    String::from("Expected an expression between { and }"),
+                ));
+            }
+            if !text.is_empty() {
+                parts.push(StringPart::Text(std::mem::take(&mut text)));
+            }
+            parts.push(StringPart::Code(i + 1, code));
+            i += close + 2;
+            continue;
+        }
+        if chars[i] == '}' {
+            return Err((
+                i,
+                "Bad interpolation",
+                String::from("Unmatched }, write \\} for a brace"),
+            ));
+        }
+        if chars[i] != '\\' {
+            text.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let escape = i;
+        i += 2;
+        match chars[escape + 1] {
+            'n' => text.push('\n'),
+            't' => text.push('\t'),
+            'r' => text.push('\r'),
+            '0' => text.push('\0'),
+            '\\' => text.push('\\'),
+            '"' => text.push('"'),
+            '{' => text.push('{'),
+            '}' => text.push('}'),
+            '\n' => {
+                while i < chars.len() - 1 && chars[i].is_whitespace() {
+                    i += 1;
+                }
+            }
+            'u' => {
+                let close = chars[i..].iter().position(|ch| *ch == '}');
+                let code = match (chars.get(i), close) {
+                    (Some('{'), Some(close)) => {
+                        let hex: String = chars[i + 1..i + close].iter().collect();
+                        i += close + 1;
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .filter(|_| hex.len() <= 6)
+                    }
+                    _ => None,
+                };
+                match code.and_then(char::from_u32) {
+                    Some(ch) => text.push(ch),
+                    None => {
+                        return Err((
+                            escape,
+                            "Bad escape",
+                            String::from("Expected a unicode escape like \\u{1F600}"),
+                        ))
+                    }
+                }
+            }
+            other => return Err((escape, "Bad escape", format!("Unknown escape \\{}", other))),
+        }
+    }
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(StringPart::Text(text));
+    }
+    Ok(parts)
+}
+
+// The pieces of a string literal token, which the lexer already checked.
+pub fn string_parts(raw: &str) -> Vec<StringPart> {
+    split_string(raw).unwrap()
+}
+
+// The value of a string literal token with its escapes decoded. Interpolations
+// are kept as written.
+pub fn string_value(raw: &str) -> String {
+    string_parts(raw)
+        .into_iter()
+        .map(|part| match part {
+            StringPart::Text(text) => text,
+            StringPart::Code(_, code) => format!("{{{}}}", code),
+        })
+        .collect()
+}
+
+// Location of the character `index` characters into a token.
+pub fn loc_within(loc: &LexTokenLoc, content: &str, index: usize) -> LexTokenLoc {
+    let before: String = content.chars().take(index).collect();
+    let line = loc.line + before.matches('\n').count() as u32;
+    let col = match before.rfind('\n') {
+        Some(newline) => before[newline + 1..].chars().count() + 1,
+        None => loc.col + index,
+    };
+    LexTokenLoc {
+        line,
+        col,
+        start: loc.start + index,
+        end: loc.start + index,
+        byte_start: loc.byte_start + before.len(),
+        byte_end: loc.byte_start + before.len(),
+    }
+}
+
+// Puts lexed source back together, the inverse of `Lexer::lex`.
+pub fn untokenize(tokens: &[LexToken], trailing: &[Trivia]) -> String {
+    let mut source = String::new();
+    for tok in tokens {
+        for trivia in &tok.trivia {
+            source.push_str(&trivia.text);
+        }
+        source.push_str(&tok.content);
+    }
+    for trivia in trailing {
+        source.push_str(&trivia.text);
+    }
+    source
 }
 
 #[derive(Clone, Debug)]
 pub struct Lexer {
-    ch: char,
-    loc: TokenLoc,
     input: Vec<char>,
+    // Position of the next character.
+    pos: usize,
+    byte: usize,
+    line: u32,
+    line_start: usize,
     tree: Vec<LexToken>,
+    // Trivia waiting for the next token, after lexing the trivia at the end of
+    // the file.
+    trailing: Vec<Trivia>,
+    // Where the input starts in the file, past the start for expressions
+    // interpolated into strings.
+    base: LexTokenLoc,
 }
 
 impl Lexer {
     pub fn new(input: Vec<char>) -> Self {
         Self {
-            ch: char::from(input[0]),
-            loc: TokenLoc {
-                col: 0,
-                line: 1,
-                line_start: 0,
-            },
             input,
+            pos: 0,
+            byte: 0,
+            line: 1,
+            line_start: 0,
             tree: vec![],
+            trailing: vec![],
+            base: LexTokenLoc {
+                line: 1,
+                col: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    // Lexes source found at `base` inside a file, locations are in that file.
+    pub fn embedded(input: Vec<char>, base: LexTokenLoc) -> Self {
+        Self {
+            line: base.line,
+            base,
+            ..Lexer::new(input)
         }
     }
 
@@ -86,276 +366,204 @@ impl Lexer {
         self.tree.to_owned()
     }
 
-    pub fn read(&mut self) {
-        self.loc.col += 1;
-        if self.loc.col >= self.input.len() {
-            self.ch = char::from(0)
-        } else {
-            self.ch = self.input[self.loc.col];
+    pub fn trailing_trivia(&self) -> Vec<Trivia> {
+        self.trailing.to_owned()
+    }
+
+    fn peek_at(&self, ahead: usize) -> char {
+        match self.input.get(self.pos + ahead) {
+            Some(ch) => *ch,
+            None => char::from(0),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn read(&mut self) {
+        let ch = self.input[self.pos];
+        self.pos += 1;
+        self.byte += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.line_start = self.pos;
         }
     }
-    pub fn peek(&mut self) -> char {
-        if self.loc.col >= self.input.len() - 1 {
-            return char::from(0);
-        } else {
-            self.input[self.loc.col + 1]
+
+    fn read_while(&mut self, pred: impl Fn(char) -> bool) {
+        while !self.at_end() && pred(self.peek_at(0)) {
+            self.read();
+        }
+    }
+
+    fn here(&self) -> LexTokenLoc {
+        let col = match self.line == self.base.line {
+            true => self.base.col + self.pos,
+            false => self.pos - self.line_start + 1,
+        };
+        LexTokenLoc {
+            line: self.line,
+            col,
+            start: self.base.start + self.pos,
+            end: self.base.start + self.pos,
+            byte_start: self.base.byte_start + self.byte,
+            byte_end: self.base.byte_start + self.byte,
+        }
+    }
+
+    // The text read since `loc` was taken, with `loc` extended to cover it.
+    fn finish(&self, mut loc: LexTokenLoc) -> (String, LexTokenLoc) {
+        let start = loc.start - self.base.start;
+        loc.end = self.base.start + self.pos;
+        loc.byte_end = self.base.byte_start + self.byte;
+        (self.input[start..self.pos].iter().collect(), loc)
+    }
+
+    fn trivia(&mut self, kind: TriviaKind, loc: LexTokenLoc) {
+        let (text, loc) = self.finish(loc);
+        self.trailing.push(Trivia { kind, text, loc });
+    }
+
+    fn token(&mut self, tok_type: TokenType, loc: LexTokenLoc) {
+        let (content, loc) = self.finish(loc);
+        self.tree.push(LexToken {
+            tok_type,
+            trivia: std::mem::take(&mut self.trailing),
+            content,
+            loc,
+        });
+    }
+
+    // Number of # in a raw string starting here, like the 1 of r#"..."#.
+    fn raw_string_hashes(&self) -> Option<usize> {
+        let hashes = self.input[self.pos + 1..]
+            .iter()
+            .take_while(|ch| **ch == '#')
+            .count();
+        match self.peek_at(hashes + 1) {
+            '"' => Some(hashes),
+            _ => None,
+        }
+    }
+
+    // Reads the `closing` characters that end a string or fails when the
+    // file ended first.
+    fn string_end(&mut self, loc: &LexTokenLoc, closing: usize) {
+        if self.at_end() {
+            diagnostic::fatal(
+                "Unterminated string",
+                loc.line,
+                loc.col,
+                String::from("Expected a closing \""),
+            );
+        }
+        for _ in 0..closing {
+            self.read();
         }
     }
 
     pub fn lex(&mut self) {
-        loop {
-            if self.ch.is_whitespace() && self.ch != '\n' && self.ch != '\t' {
-                self.read()
-            } else if self.ch.is_alphabetic() {
-                let start_col = self.loc.col;
-                let mut name: String = String::from(self.ch);
-
-                while self.peek().is_alphanumeric() {
+        while !self.at_end() {
+            let loc = self.here();
+            let ch = self.peek_at(0);
+            if ch == '\n' {
+                self.read();
+                self.trivia(TriviaKind::Newline, loc);
+            } else if ch.is_whitespace() {
+                self.read_while(|ch| ch.is_whitespace() && ch != '\n');
+                self.trivia(TriviaKind::Whitespace, loc);
+            } else if ch == '/' && self.peek_at(1) == '/' {
+                self.read_while(|ch| ch != '\n');
+                self.trivia(TriviaKind::Comment, loc);
+            } else if ch == 'r' && self.raw_string_hashes().is_some() {
+                let hashes = self.raw_string_hashes().unwrap();
+                let mut close = vec!['"'];
+                close.extend("#".repeat(hashes).chars());
+                for _ in 0..hashes + 2 {
+                    self.read(); // consume r, # and the starting "
+                }
+                while !self.at_end() && !self.input[self.pos..].starts_with(&close) {
                     self.read();
-                    name += &self.ch.to_string();
-                    //        println!("{}", self.loc.col >= self.input.len());
                 }
-
+                self.string_end(&loc, hashes + 1);
+                self.token(TokenType::STRING, loc);
+            } else if ch.is_alphabetic() {
                 self.read();
+                self.read_while(|ch| ch.is_alphanumeric() || ch == '_');
+                let (name, _) = self.finish(loc.clone());
                 match name.as_str() {
-                    "end" => self.tree.push(LexToken {
-                        tok_type: TokenType::KEYWORD,
-                        content: name,
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: start_col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
-
-                    _ => self.tree.push(LexToken {
-                        tok_type: TokenType::LABEL,
-                        content: name,
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: start_col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
+                    "end" => self.token(TokenType::KEYWORD, loc),
+                    "not" => self.token(TokenType::NOT, loc),
+                    "as" => self.token(TokenType::AS, loc),
+                    _ => self.token(TokenType::LABEL, loc),
                 }
-            } else if self.ch == '"' {
-                let start_col = self.loc.col;
+            } else if ch == '"' {
                 self.read(); // consume starting "
-                let mut str_content: String = String::from(self.ch);
-
-                loop {
-                    if self.peek() == '"' {
-                        break self.read();
+                while !self.at_end() && self.peek_at(0) != '"' {
+                    if self.peek_at(0) == '\\' && self.pos + 1 < self.input.len() {
+                        self.read();
                     }
                     self.read();
-                    str_content += &self.ch.to_string();
                 }
-                self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::STRING,
-                    content: "\"".to_string() + &str_content + "\"",
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: start_col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch.is_numeric() {
-                let start_col = self.loc.col;
-                let mut num: String = String::from(self.ch);
-
-                while self.peek().is_numeric() || self.peek() == '.' {
-                    self.read();
-                    num += &self.ch.to_string();
-                    //        println!("{}", self.loc.col >= self.input.len());
+                self.string_end(&loc, 1);
+                let (raw, raw_loc) = self.finish(loc.clone());
+                if let Err((index, kind, message)) = split_string(&raw) {
+                    // Point at the problem itself, which may be lines into the string.
+                    let at = loc_within(&raw_loc, &raw, index);
+                    diagnostic::fatal(kind, at.line, at.col, message);
                 }
-
-                self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::NUMBER,
-                    content: num,
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: start_col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == '(' {
+                self.token(TokenType::STRING, loc);
+            } else if ch.is_numeric() {
+                self.read_while(|ch| ch.is_numeric() || ch == '.');
+                self.token(TokenType::NUMBER, loc);
+            } else if ch == '+' && self.peek_at(1) == '+' {
                 self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::LPAREN,
-                    content: String::from("("),
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: self.loc.col - 1,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == ')' {
                 self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::RPAREN,
-                    content: String::from(")"),
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: self.loc.col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == '!' {
-                self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::MMARK,
-                    content: String::from("!"),
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: self.loc.col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == ';' {
-                self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::SEMCOLON,
-                    content: String::from(";"),
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: self.loc.col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == '-' && self.peek().is_numeric() {
-                let start_col = self.loc.col;
-
-                let mut num: String = String::from(self.ch);
-
-                while self.peek().is_numeric() || self.peek() == '.' {
-                    self.read();
-                    num += &self.ch.to_string();
-                }
-
+                self.token(TokenType::CONCAT, loc);
+            } else if ch == '*' && self.peek_at(1) == '*' {
                 self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::NEGNUMBER,
-                    content: num,
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: start_col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == '/' && self.peek() == '/' {
-                while self.peek() != '\n' {
-                    self.read();
-                }
                 self.read();
-            } else if self.ch == ',' {
+                self.token(TokenType::POWBIN, loc);
+            } else if (ch == '<' || ch == '>') && self.peek_at(1) == ch {
                 self.read();
-                self.tree.push(LexToken {
-                    tok_type: TokenType::COMMA,
-                    content: ",".to_string(),
-                    loc: LexTokenLoc {
-                        line_start: self.loc.line_start,
-                        col: self.loc.col,
-                        end_col: self.loc.col,
-                        line: self.loc.line,
-                    },
-                })
-            } else if self.ch == '+'
-                || self.ch == '-'
-                || (self.ch == '/' && self.peek() != '/')
-                || self.ch == '*'
-                || self.ch == ':'
-            {
-                match self.ch {
-                    '+' => self.tree.push(LexToken {
-                        tok_type: TokenType::PLUSBIN,
-                        content: String::from("+"),
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: self.loc.col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
-                    '-' => self.tree.push(LexToken {
-                        tok_type: TokenType::SUBBIN,
-                        content: String::from("+"),
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: self.loc.col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
-
-                    '/' => self.tree.push(LexToken {
-                        tok_type: TokenType::DIVBIN,
-                        content: String::from("/"),
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: self.loc.col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
-                    '*' => self.tree.push(LexToken {
-                        tok_type: TokenType::MULBIN,
-                        content: String::from("*"),
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: self.loc.col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
-                    ':' => self.tree.push(LexToken {
-                        tok_type: TokenType::COLON,
-                        content: String::from(":"),
-                        loc: LexTokenLoc {
-                            line_start: self.loc.line_start,
-                            col: self.loc.col,
-                            end_col: self.loc.col,
-                            line: self.loc.line,
-                        },
-                    }),
-                    _ => unimplemented!(),
-                }
                 self.read();
-            } else if self.ch == '\n' {
-                self.loc.line_start = self.loc.col + 1;
-                self.loc.line += 1;
+                let tok_type = if ch == '<' {
+                    TokenType::SHL
+                } else {
+                    TokenType::SHR
+                };
+                self.token(tok_type, loc);
+            } else if ch == '-' && self.peek_at(1).is_numeric() {
                 self.read();
-            } else if self.ch == char::from(0) {
-                break;
+                self.read_while(|ch| ch.is_numeric() || ch == '.');
+                self.token(TokenType::NEGNUMBER, loc);
             } else {
-                let mut code: String = self.input.clone().into_iter().collect();
-
-                code.replace_range(
-                    self.loc.col..self.loc.col + 1,
-                    &format!(
-                        "{}{}{}",
-                        termion::style::Bold,
-                        color::Fg(color::Red),
-                        code.chars().collect::<Vec<char>>()[self.loc.col]
+                let tok_type = match ch {
+                    '(' => TokenType::LPAREN,
+                    ')' => TokenType::RPAREN,
+                    '!' => TokenType::MMARK,
+                    ';' => TokenType::SEMCOLON,
+                    ',' => TokenType::COMMA,
+                    ':' => TokenType::COLON,
+                    '+' => TokenType::PLUSBIN,
+                    '-' => TokenType::SUBBIN,
+                    '*' => TokenType::MULBIN,
+                    '/' => TokenType::DIVBIN,
+                    '%' => TokenType::MODBIN,
+                    '&' => TokenType::BITAND,
+                    '|' => TokenType::BITOR,
+                    '^' => TokenType::BITXOR,
+                    '~' => TokenType::BITNOT,
+                    _ => diagnostic::fatal(
+                        "Unexpected token",
+                        loc.line,
+                        loc.col,
+                        format!("{:?}", ch),
                     ),
-                );
-                let header = format!(
-                    "Unexpected token ({line}:{col}): ",
-                    line = self.loc.line,
-                    col = self.loc.col,
-                );
-                eprintln!("{}{}", header, code);
-                std::process::exit(1);
+                };
+                self.read();
+                self.token(tok_type, loc);
             }
         }
     }
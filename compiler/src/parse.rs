@@ -1,6 +1,12 @@
+use crate::diagnostic;
+use crate::loc_within;
 use crate::som_std;
+use crate::string_parts;
+use crate::string_value;
 use crate::LexToken;
 use crate::LexTokenLoc;
+use crate::Lexer;
+use crate::StringPart;
 use crate::TokenType;
 use std::collections::HashMap;
 
@@ -18,6 +24,7 @@ pub enum ParseType {
     LABEL,
     VARDEF,
     COMMA,
+    TEST,
 }
 
 #[derive(Clone, Debug, PartialEq, Hash, Eq)]
@@ -39,6 +46,36 @@ pub enum BinOperand {
     SUB,
     MUL,
     DIV,
+    MOD,
+    POW,
+    BITAND,
+    BITOR,
+    BITXOR,
+    BITNOT,
+    SHL,
+    SHR,
+    NEG,
+    NOT,
+}
+
+impl BinOperand {
+    // Operators that only make sense on whole numbers.
+    pub fn whole_numbers_only(&self) -> bool {
+        matches!(
+            self,
+            BinOperand::BITAND
+                | BinOperand::BITOR
+                | BinOperand::BITXOR
+                | BinOperand::BITNOT
+                | BinOperand::SHL
+                | BinOperand::SHR
+                | BinOperand::NOT
+        )
+    }
+
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, BinOperand::BITNOT | BinOperand::NEG | BinOperand::NOT)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -57,6 +94,12 @@ pub struct FuncDef {
     pub is_std: bool,
 }
 
+#[derive(Clone, Debug)]
+pub struct TestBlock {
+    pub name: String,
+    pub body: Vec<ParseTok>,
+}
+
 #[derive(Clone, Debug)]
 pub struct FuncRet {
     pub value: Option<ParseTok>,
@@ -73,7 +116,7 @@ pub struct FnCall {
 #[derive(Clone, Debug)]
 pub struct BinSeg {
     pub left: ParseTok,
-    pub right: Option<ParseTok>,
+    pub right: ParseTok,
 
     pub operation: BinOperand,
 }
@@ -81,7 +124,17 @@ pub struct BinSeg {
 #[derive(Clone, Debug)]
 pub struct Exp {
     pub exp_type: Primitives,
-    pub body: Vec<BinSeg>,
+    pub body: Box<BinSeg>,
+}
+
+// A piece of an expression while it is put in order.
+enum ExpItem {
+    Value(Vec<LexToken>),
+    Op(LexToken),
+    Open,
+    Close(LexToken),
+    // `as` and the type after it.
+    Cast(LexToken, Primitives),
 }
 
 #[derive(Clone, Debug)]
@@ -106,12 +159,17 @@ pub struct Label {
 pub struct StringT {
     pub length: usize,
     pub content: String,
+    // Text and values of an interpolated string in order, empty for plain ones.
+    pub template: Vec<ParseTok>,
 }
 #[derive(Clone, Debug)]
+// start_col and end_col are character offsets from the start of the file,
+// line and col where the node starts counting from 1.
 pub struct ParseLoc {
     pub start_col: usize,
     pub end_col: usize,
     pub line: u32,
+    pub col: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -127,6 +185,7 @@ pub struct ParseTok {
     pub fncall: Box<Option<FnCall>>,
     pub fnmake: Box<Option<FuncDef>>,
     pub fnreturn: Box<Option<FuncRet>>,
+    pub test: Box<Option<TestBlock>>,
 }
 
 #[derive(Clone, Debug)]
@@ -139,9 +198,25 @@ pub struct Parser {
 
     pub curr_scope: HashMap<String, ParseTok>,
 }
+fn tok_loc(tok: &LexToken) -> ParseLoc {
+    ParseLoc {
+        start_col: tok.loc.start,
+        end_col: tok.loc.end,
+        line: tok.loc.line,
+        col: tok.loc.col,
+    }
+}
 pub fn prim_eq(a: &Primitives, b: &Primitives) -> bool {
     std::mem::discriminant(a) == std::mem::discriminant(b)
 }
+// The name operators are ranked by, a - that negates keeps its content but
+// binds like the other prefix operators.
+fn op_name(op: &LexToken) -> &str {
+    match op.tok_type {
+        TokenType::NEG => "neg",
+        _ => &op.content,
+    }
+}
 fn op_prec(op: &str) -> u16 {
     match op {
         "+" => 10,
@@ -149,6 +224,23 @@ fn op_prec(op: &str) -> u16 {
 
         "*" => 20,
         "/" => 20,
+        "%" => 20,
+
+        "**" => 25,
+
+        "<<" => 8,
+        ">>" => 8,
+        "&" => 6,
+        "^" => 5,
+        "|" => 4,
+        // Prefix operators bind tighter than everything but **, so -x ** 2 is
+        // -(x ** 2).
+        "~" => 24,
+        "neg" => 24,
+        "not" => 24,
+        // Tighter than arithmetic but not prefix operators, -x as f32 is
+        // (-x) as f32.
+        "as" => 23,
 
         "(" => 40,
         ")" => 40,
@@ -163,12 +255,34 @@ fn op_prec(op: &str) -> u16 {
         _ => 0,
     }
 }
-fn _op_asso(op: &str) -> &str {
+fn is_bin_op(tok_type: &TokenType) -> bool {
+    matches!(
+        tok_type,
+        TokenType::PLUSBIN
+            | TokenType::SUBBIN
+            | TokenType::MULBIN
+            | TokenType::DIVBIN
+            | TokenType::MODBIN
+            | TokenType::POWBIN
+            | TokenType::BITAND
+            | TokenType::BITOR
+            | TokenType::BITXOR
+            | TokenType::SHL
+            | TokenType::SHR
+    )
+}
+fn op_asso(op: &str) -> &str {
     match op {
         "+" => "left",
         "-" => "left",
         "/" => "left",
         "*" => "left",
+        "%" => "left",
+        "<<" => "left",
+        ">>" => "left",
+        "&" => "left",
+        "^" => "left",
+        "|" => "left",
 
         // comparison
         "&&" => "left",
@@ -203,13 +317,22 @@ pub fn keyword_types(prim: Primitives) -> Primitives {
     }
 }
 
-fn get_prim(tok: ParseTok) -> Primitives {
+pub fn get_prim(tok: ParseTok) -> Primitives {
     match tok.tok_type {
         ParseType::NUMBER => tok.number.unwrap().num_type,
         ParseType::STRING => Primitives::STRING,
         ParseType::OPERATOR => Primitives::OPERATOR,
         ParseType::EXP => tok.expression.unwrap().exp_type,
-        ParseType::FNCALL => tok.fncall.unwrap().ret_type.unwrap(),
+        ParseType::FNCALL => match tok.fncall.clone().unwrap().ret_type {
+            Some(ret_type) => ret_type,
+            // Calls for what they do, like print, used as a value.
+            None => diagnostic::fatal(
+                "Bad type",
+                tok.location.line,
+                tok.location.col,
+                format!("{} has no value", tok.fncall.unwrap().name),
+            ),
+        },
         ParseType::LABEL => {
             if tok.clone().ident.unwrap().var_type == Primitives::INSCOPE("i8".to_string()) {
                 Primitives::INT(8)
@@ -237,13 +360,12 @@ fn get_prim(tok: ParseTok) -> Primitives {
         }
 
         _ => {
-            println!(
-                "Unknown Primitive ({line},{col}): {:?}",
-                tok.tok_type,
-                line = tok.location.line,
-                col = tok.location.start_col
+            diagnostic::fatal(
+                "Unknown Primitive",
+                tok.location.line,
+                tok.location.col,
+                format!("{:?}", tok.tok_type),
             );
-            std::process::exit(1);
         }
     }
 }
@@ -259,48 +381,68 @@ impl Parser {
         }
     }
 
+    // Sits on the line after the last token so statements that read up to the
+    // end of a line stop there.
+    fn eof(&self) -> LexToken {
+        let last = self.lex_tree.last().unwrap().loc();
+        LexToken {
+            tok_type: TokenType::EOF,
+            trivia: vec![],
+            content: String::from(""),
+            loc: LexTokenLoc {
+                line: last.line + 1,
+                col: 1,
+                start: last.end,
+                end: last.end,
+                byte_start: last.byte_end,
+                byte_end: last.byte_end,
+            },
+        }
+    }
+
+    fn here(&self) -> ParseLoc {
+        tok_loc(&self.tok)
+    }
+
     pub fn next_tok(&mut self) {
         self.lex_id += 1;
         if self.lex_id >= self.lex_tree.len() {
-            self.tok = LexToken {
-                tok_type: TokenType::EOF,
-                content: String::from(""),
-                loc: LexTokenLoc {
-                    line: self.lex_tree.last().unwrap().loc.line + 1,
-                    line_start: self.lex_tree.last().unwrap().loc.line_start + 1,
-                    col: self.lex_tree.last().unwrap().loc.col + 1,
-                    end_col: self.lex_tree.last().unwrap().loc.end_col + 1,
-                },
-            }
+            self.tok = self.eof()
         } else {
             self.tok = self.lex_tree[self.lex_id].to_owned();
         }
     }
 
     pub fn peek(&mut self) -> LexToken {
-        if self.lex_id + 1 >= self.lex_tree.len() {
-            LexToken {
-                tok_type: TokenType::EOF,
-                content: String::from(""),
-                loc: LexTokenLoc {
-                    line: self.lex_tree.last().unwrap().loc.line + 1,
-                    line_start: self.lex_tree.last().unwrap().loc.line_start + 1,
-                    col: self.lex_tree.last().unwrap().loc.col + 1,
-                    end_col: self.lex_tree.last().unwrap().loc.end_col + 1,
-                },
-            }
+        self.peek_at(1)
+    }
+
+    fn peek_at(&self, ahead: usize) -> LexToken {
+        if self.lex_id + ahead >= self.lex_tree.len() {
+            self.eof()
         } else {
-            self.lex_tree[self.lex_id + 1].clone()
+            self.lex_tree[self.lex_id + ahead].clone()
+        }
+    }
+
+    // Stops with a diagnostic when a construct runs off the end of the file
+    // instead of looping forever waiting for its closing token.
+    fn expect_more(&self, line: u32, col: usize, message: String) {
+        if self.tok.tok_type == TokenType::EOF {
+            diagnostic::fatal("Unexpected end of file", line, col, message);
         }
     }
 
     pub fn parse_exp(&mut self) -> ParseTok {
         let mut paren_count = 0;
-        let start_col = self.tok.loc.col;
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
         let mut end_col = start_col + 1;
         let sub_tree: &mut Vec<LexToken> = &mut vec![];
 
+        let line = self.tok.loc.line;
         loop {
+            self.expect_more(line, col, String::from("Expression is missing )"));
             if self.tok.tok_type == TokenType::LPAREN {
                 paren_count += 1;
                 sub_tree.push(self.tok.clone());
@@ -312,128 +454,480 @@ impl Parser {
             }
 
             if self.tok.tok_type == TokenType::RPAREN && paren_count == 0 {
-                end_col = self.tok.loc.end_col;
+                end_col = self.tok.loc.end;
                 break;
             }
             self.next_tok();
         }
 
-        let mut stack: Vec<LexToken> = vec![];
-        let mut op_stack: Vec<LexToken> = vec![];
+        let mut depth = 0;
+        let mut joins = false;
+        for tok in sub_tree.iter() {
+            match tok.tok_type {
+                TokenType::LPAREN => depth += 1,
+                TokenType::RPAREN => depth -= 1,
+                TokenType::CONCAT => joins |= depth == 1,
+                _ => {}
+            }
+        }
+        if joins {
+            self.next_tok();
+            let location = ParseLoc {
+                start_col,
+                end_col,
+                line,
+                col,
+            };
+            return self.parse_concat(&sub_tree[1..sub_tree.len() - 1], location);
+        }
 
-        for lex_t in &mut sub_tree.to_owned().clone() {
-            let current = lex_t.clone();
-            if current.tok_type == TokenType::NUMBER || current.tok_type == TokenType::NEGNUMBER {
-                stack.push(current.clone());
-            } else if current.tok_type == TokenType::SUBBIN
-                || current.tok_type == TokenType::PLUSBIN
-                || current.tok_type == TokenType::DIVBIN
-                || current.tok_type == TokenType::MULBIN
-            {
-                while op_stack.len() != 0
-                    && op_prec(&current.content) <= op_prec(&op_stack.last().unwrap().content)
-                    && op_stack.last().unwrap().content != "("
+        self.next_tok();
+        let items = self.exp_items(&sub_tree[1..sub_tree.len() - 1]);
+        if items.is_empty() {
+            diagnostic::fatal(
+                "Syntax Error",
+                line,
+                col,
+                String::from("Expected a value between ( and )"),
+            );
+        }
+        let mut tree = self.exp_tree(items);
+        tree.location = ParseLoc {
+            start_col,
+            end_col,
+            line,
+            col,
+        };
+        tree
+    }
+
+    // Splits the inside of an expression into values, operators and
+    // parentheses. Calls and parentheses holding ++ are single values.
+    fn exp_items(&self, tokens: &[LexToken]) -> Vec<ExpItem> {
+        let mut items = vec![];
+        let mut i = 0;
+        while i < tokens.len() {
+            let tok = &tokens[i];
+            let group = (tok.tok_type == TokenType::MMARK
+                && tokens.get(i + 1).map(|t| &t.tok_type) == Some(&TokenType::LPAREN))
+                || (tok.tok_type == TokenType::LPAREN && joins_strings(&tokens[i..]));
+            if group {
+                let start = i;
+                let mut depth = 0;
+                loop {
+                    match tokens[i].tok_type {
+                        TokenType::LPAREN => depth += 1,
+                        TokenType::RPAREN => depth -= 1,
+                        _ => {}
+                    }
+                    i += 1;
+                    if depth == 0 && tokens[i - 1].tok_type == TokenType::RPAREN {
+                        break;
+                    }
+                }
+                items.push(ExpItem::Value(tokens[start..i].to_vec()));
+                continue;
+            }
+            match tok.tok_type {
+                TokenType::LPAREN => items.push(ExpItem::Open),
+                TokenType::RPAREN => items.push(ExpItem::Close(tok.clone())),
+                // 5-3 lexes as 5 and -3.
+                TokenType::NEGNUMBER
+                    if matches!(items.last(), Some(ExpItem::Value(_) | ExpItem::Close(_))) =>
                 {
-                    let curr_op = op_stack.pop();
-                    if curr_op.is_some() {
-                        stack.push(curr_op.unwrap())
+                    let mut minus = tok.clone();
+                    minus.tok_type = TokenType::SUBBIN;
+                    minus.content = String::from("-");
+                    let mut number = tok.clone();
+                    number.tok_type = TokenType::NUMBER;
+                    number.content = tok.content[1..].to_string();
+                    items.push(ExpItem::Op(minus));
+                    items.push(ExpItem::Value(vec![number]));
+                }
+                TokenType::NUMBER | TokenType::NEGNUMBER | TokenType::LABEL | TokenType::STRING => {
+                    items.push(ExpItem::Value(vec![tok.clone()]))
+                }
+                TokenType::AS => {
+                    let target = tokens
+                        .get(i + 1)
+                        .filter(|t| t.tok_type == TokenType::LABEL)
+                        .map(|t| keyword_types(Primitives::INSCOPE(t.content.clone())));
+                    match target {
+                        Some(
+                            target @ (Primitives::INT(_)
+                            | Primitives::FLOAT(_)
+                            | Primitives::STRING),
+                        ) => items.push(ExpItem::Cast(tok.clone(), target)),
+                        _ => diagnostic::fatal(
+                            "Syntax Error",
+                            tok.loc.line,
+                            tok.loc.col,
+                            String::from("Expected a type like i32, f32 or str after as"),
+                        ),
                     }
+                    i += 1; // the type
+                }
+                _ if is_bin_op(&tok.tok_type)
+                    || tok.tok_type == TokenType::BITNOT
+                    || tok.tok_type == TokenType::NOT =>
+                {
+                    items.push(ExpItem::Op(tok.clone()))
                 }
-                op_stack.push(current);
-            } else if current.tok_type == TokenType::LPAREN {
-                op_stack.push(current);
-            } else if current.tok_type == TokenType::RPAREN {
-                let mut found_paren_match = false;
-                while op_stack.last().is_some() && op_stack.last().unwrap().content != ")" {
-                    if op_stack.last().unwrap().tok_type == TokenType::LPAREN {
-                        op_stack.pop();
-                        found_paren_match = true;
-                    } else {
-                        stack.push(op_stack.pop().unwrap());
+                _ => diagnostic::fatal(
+                    "Syntax Error",
+                    tok.loc.line,
+                    tok.loc.col,
+                    format!("Unexpected {} in expression", tok.content),
+                ),
+            }
+            i += 1;
+        }
+        items
+    }
+
+    // Orders the items with the shunting yard and builds the tree from the
+    // result, every operator node holding its two operands.
+    fn exp_tree(&self, items: Vec<ExpItem>) -> ParseTok {
+        let mut output: Vec<ExpItem> = vec![];
+        let mut ops: Vec<Option<LexToken>> = vec![]; // None is an open parenthesis
+        let mut follows_value = false;
+        let mut last_op: Option<LexToken> = None;
+        for item in items {
+            match item {
+                ExpItem::Value(value) => {
+                    if follows_value {
+                        diagnostic::fatal(
+                            "Syntax Error",
+                            value[0].loc.line,
+                            value[0].loc.col,
+                            format!("Expected an operator before {}", value[0].content),
+                        );
                     }
-                    if op_stack.len() == 0 {
-                        break;
+                    output.push(ExpItem::Value(value));
+                    follows_value = true;
+                }
+                ExpItem::Open => {
+                    ops.push(None);
+                    follows_value = false;
+                }
+                ExpItem::Close(close) => {
+                    if !follows_value {
+                        diagnostic::fatal(
+                            "Syntax Error",
+                            close.loc.line,
+                            close.loc.col,
+                            String::from("Expected a value before )"),
+                        );
+                    }
+                    while let Some(Some(op)) = ops.pop() {
+                        output.push(ExpItem::Op(op));
                     }
+                    follows_value = true;
                 }
-                if found_paren_match == false {
-                    let mut content: String = String::from("");
-                    for s in &mut *sub_tree {
-                        content += &s.content
+                ExpItem::Cast(cast, target) => {
+                    if !follows_value {
+                        diagnostic::fatal(
+                            "Syntax Error",
+                            cast.loc.line,
+                            cast.loc.col,
+                            String::from("Expected a value before as"),
+                        );
+                    }
+                    // Applies to the value before it at once, after any
+                    // prefix operators in front of that value.
+                    while let Some(Some(top)) = ops.last() {
+                        if op_prec(op_name(top)) <= op_prec(op_name(&cast)) {
+                            break;
+                        }
+                        output.push(ExpItem::Op(ops.pop().unwrap().unwrap()));
                     }
-                    println!(
-                        "Syntax Error ({line}:{col}): {content}",
-                        line = current.loc.line,
-                        col = sub_tree.first().unwrap().loc.col,
-                        content = content
-                    )
+                    output.push(ExpItem::Cast(cast, target));
+                }
+                ExpItem::Op(mut op) => {
+                    // A - with no value before it negates what follows.
+                    if op.tok_type == TokenType::SUBBIN && !follows_value {
+                        op.tok_type = TokenType::NEG;
+                    }
+                    let prefix = bin_operand(&op).is_prefix();
+                    if prefix == follows_value {
+                        diagnostic::fatal(
+                            "Syntax Error",
+                            op.loc.line,
+                            op.loc.col,
+                            match prefix {
+                                true => format!("Expected an operator before {}", op.content),
+                                false => format!("Expected a value before {}", op.content),
+                            },
+                        );
+                    }
+                    // Prefix operators wait for their operand, binary ones
+                    // first finish what binds tighter.
+                    let binds_left = op_asso(op_name(&op)) == "left";
+                    if !prefix {
+                        while let Some(Some(top)) = ops.last() {
+                            let tighter = op_prec(op_name(&op)) < op_prec(op_name(top))
+                                || binds_left && op_prec(op_name(&op)) == op_prec(op_name(top));
+                            if !tighter {
+                                break;
+                            }
+                            output.push(ExpItem::Op(ops.pop().unwrap().unwrap()));
+                        }
+                    }
+                    last_op = Some(op.clone());
+                    ops.push(Some(op));
+                    follows_value = false;
                 }
-            } else {
-                stack.push(current);
             }
         }
+        if let (false, Some(op)) = (follows_value, last_op) {
+            diagnostic::fatal(
+                "Syntax Error",
+                op.loc.line,
+                op.loc.col,
+                format!("Expected a value after {}", op.content),
+            );
+        }
+        while let Some(op) = ops.pop() {
+            output.extend(op.map(ExpItem::Op));
+        }
 
-        for op in op_stack.to_owned() {
-            if op.content != "(" {
-                stack.push(op_stack.pop().unwrap())
+        let mut values: Vec<ParseTok> = vec![];
+        for item in output {
+            match item {
+                ExpItem::Value(tokens) => values.push(self.exp_value(tokens)),
+                ExpItem::Op(op) => {
+                    let right = values.pop().unwrap();
+                    let operation = bin_operand(&op);
+                    let left = match operation.is_prefix() {
+                        // Prefix operators take a constant 0 on the left, so
+                        // they fit the same nodes as everything else.
+                        true => zero(get_prim(right.clone()), &op),
+                        false => values.pop().unwrap(),
+                    };
+                    values.push(self.exp_node(&op, operation, left, right));
+                }
+                ExpItem::Cast(cast, target) => {
+                    let value = values.pop().unwrap();
+                    check_literal(&value, &target);
+                    values.push(convert(value, target, tok_loc(&cast)));
+                }
+                _ => unreachable!(),
             }
         }
+        values.pop().unwrap()
+    }
 
-        //println!("{:?}", sub_tree);
-        //println!("{:#?}", stack);
-        stack.insert(
-            0,
-            LexToken {
-                tok_type: TokenType::LABEL,
-                content: "RPN".to_string(),
-                loc: LexTokenLoc {
-                    col: 0,
-                    end_col: 0,
-                    line_start: 0,
-                    line: self.tok.loc.line,
-                },
-            },
-        );
-        stack.insert(
-            1,
-            LexToken {
-                tok_type: TokenType::MMARK,
-                content: "!".to_string(),
-                loc: LexTokenLoc {
-                    col: 0,
-                    end_col: 0,
-                    line_start: 0,
-                    line: self.tok.loc.line,
-                },
-            },
-        );
-        let mut bin_tree = Parser::new(stack.clone(), self.file.clone(), self.curr_scope.clone());
-        // println!("{:#?}", &stack);
-        let out = bin_tree.parse();
+    // Parses one operand of an expression.
+    fn exp_value(&self, tokens: Vec<LexToken>) -> ParseTok {
+        let mut parser = Parser::new(tokens.clone(), self.file.clone(), self.curr_scope.clone());
+        let value = parser.parse();
+        if parser.tok.tok_type != TokenType::EOF {
+            diagnostic::fatal(
+                "Syntax Error",
+                parser.tok.loc.line,
+                parser.tok.loc.col,
+                format!("Expected an operator instead got {}", parser.tok.content),
+            );
+        }
+        if value.tok_type == ParseType::FNCALL && value.fncall.clone().unwrap().ret_type.is_none() {
+            diagnostic::fatal(
+                "Bad type",
+                tokens[0].loc.line,
+                tokens[0].loc.col,
+                format!(
+                    "Function {} does not return a value",
+                    value.fncall.clone().unwrap().name
+                ),
+            );
+        }
+        value
+    }
 
-        self.next_tok();
-        if out.expression.is_some() {
-            ParseTok {
-                tok_type: ParseType::EXP,
-                location: ParseLoc {
-                    line: stack.last().unwrap().loc.line,
-                    start_col,
-                    end_col,
-                },
-                number: None,
-                expression: Some(Exp {
-                    exp_type: out.clone().expression.unwrap().exp_type,
-                    body: out.clone().expression.unwrap().body,
+    fn exp_node(
+        &self,
+        op: &LexToken,
+        operation: BinOperand,
+        left: ParseTok,
+        right: ParseTok,
+    ) -> ParseTok {
+        let mut left = left;
+        let mut right = right;
+        let mut left_type = get_prim(left.clone());
+        let right_type = get_prim(right.clone());
+        if widens(&left_type, &right_type) {
+            left = convert(left, right_type.clone(), tok_loc(op));
+            left_type = right_type.clone();
+        } else if widens(&right_type, &left_type) {
+            right = convert(right, left_type.clone(), tok_loc(op));
+        }
+        if !prim_eq(&left_type, &right_type) {
+            diagnostic::fatal(
+                "Bad Types",
+                op.loc.line,
+                op.loc.col,
+                format!("Cannot use type {:?} with type {:?}", left_type, right_type),
+            );
+        }
+        let content = match operation {
+            BinOperand::NEG => "-",
+            _ => op.content.as_str(),
+        };
+        let whole = prim_eq(&left_type, &Primitives::INT(32))
+            || prim_eq(&left_type, &Primitives::SIGINT(32));
+        if operation.whole_numbers_only() && !whole {
+            diagnostic::fatal(
+                "Bad types",
+                op.loc.line,
+                op.loc.col,
+                format!(
+                    "Operator {} only works on whole numbers instead got type {:?}",
+                    content, left_type
+                ),
+            );
+        }
+        if !whole && !prim_eq(&left_type, &Primitives::FLOAT(32)) {
+            diagnostic::fatal(
+                "Bad types",
+                op.loc.line,
+                op.loc.col,
+                format!(
+                    "Operator {} only works on numbers instead got type {:?}",
+                    content, left_type
+                ),
+            );
+        }
+        ParseTok {
+            tok_type: ParseType::EXP,
+            location: ParseLoc {
+                start_col: op.loc.start,
+                end_col: op.loc.end,
+                line: op.loc.line,
+                col: op.loc.col,
+            },
+            number: None,
+            expression: Some(Exp {
+                exp_type: left_type,
+                body: Box::new(BinSeg {
+                    left,
+                    right,
+                    operation,
                 }),
-                string: None,
-                operand: None,
-                fnreturn: Box::new(None),
-                ident: None,
-                variable: Box::new(None),
-                fncall: Box::new(None),
-                fnmake: Box::new(None),
+            }),
+            string: None,
+            operand: None,
+            fnreturn: Box::new(None),
+            ident: None,
+            test: Box::new(None),
+            variable: Box::new(None),
+            fncall: Box::new(None),
+            fnmake: Box::new(None),
+        }
+    }
+
+    // Parses the inside of `(a ++ b ++ c)` into calls of concat.
+    fn parse_concat(&self, tokens: &[LexToken], location: ParseLoc) -> ParseTok {
+        let mut operands: Vec<Vec<LexToken>> = vec![vec![]];
+        let mut depth = 0;
+        for tok in tokens {
+            match tok.tok_type {
+                TokenType::LPAREN => depth += 1,
+                TokenType::RPAREN => depth -= 1,
+                _ => {}
+            }
+            if tok.tok_type == TokenType::CONCAT && depth == 0 {
+                operands.push(vec![]);
+            } else {
+                operands.last_mut().unwrap().push(tok.clone());
             }
-        } else {
-            unimplemented!()
+        }
+
+        let mut joined: Option<ParseTok> = None;
+        for operand in operands {
+            if operand.is_empty() {
+                diagnostic::fatal(
+                    "Syntax Error",
+                    location.line,
+                    location.col,
+                    String::from("++ needs a string on both sides"),
+                );
+            }
+            let mut parser =
+                Parser::new(operand.clone(), self.file.clone(), self.curr_scope.clone());
+            let value = parser.parse();
+            if parser.tok.tok_type != TokenType::EOF {
+                diagnostic::fatal(
+                    "Syntax Error",
+                    parser.tok.loc.line,
+                    parser.tok.loc.col,
+                    format!("Expected ++ or ) instead got {}", parser.tok.content),
+                );
+            }
+            if value.tok_type == ParseType::FNCALL
+                && value.fncall.clone().unwrap().ret_type.is_none()
+            {
+                diagnostic::fatal(
+                    "Bad type",
+                    operand[0].loc.line,
+                    operand[0].loc.col,
+                    format!(
+                        "Function {} does not return a value",
+                        value.fncall.clone().unwrap().name
+                    ),
+                );
+            }
+            let value_type = get_prim(value.clone());
+            if !prim_eq(&value_type, &Primitives::STRING) {
+                diagnostic::fatal(
+                    "Bad types",
+                    operand[0].loc.line,
+                    operand[0].loc.col,
+                    format!(
+                        "++ joins strings instead got type {:?}, to_string turns numbers into strings",
+                        value_type
+                    ),
+                );
+            }
+            joined = Some(match joined {
+                None => value,
+                Some(left) => ParseTok {
+                    tok_type: ParseType::FNCALL,
+                    location: location.clone(),
+                    expression: None,
+                    number: None,
+                    fnreturn: Box::new(None),
+                    string: None,
+                    operand: None,
+                    ident: None,
+                    test: Box::new(None),
+                    variable: Box::new(None),
+                    fncall: Box::new(Some(FnCall {
+                        name: String::from("concat"),
+                        is_std: true,
+                        args: vec![left, value],
+                        ret_type: Some(Primitives::STRING),
+                    })),
+                    fnmake: Box::new(None),
+                },
+            });
+        }
+        joined.unwrap()
+    }
+
+    // Whole number literals are i32 unless they only fit in an i64.
+    fn whole_literal(&self) -> Number {
+        let number = match self.tok.content.parse::<i64>() {
+            Ok(number) => number,
+            Err(_) => diagnostic::fatal(
+                "Number out of range",
+                self.tok.loc.line,
+                self.tok.loc.col,
+                format!("{} does not fit in any whole number type", self.tok.content),
+            ),
+        };
+        Number {
+            num_type: Primitives::INT(if fits(number, 32) { 32 } else { 64 }),
+            number: Some(number),
+            float: None,
         }
     }
 
@@ -442,11 +936,7 @@ impl Parser {
         if self.tok.content.contains(".") && self.tok.content.starts_with("-") == false {
             int = ParseTok {
                 tok_type: ParseType::NUMBER,
-                location: ParseLoc {
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
-                    line: self.tok.loc.line,
-                },
+                location: self.here(),
                 number: Some(Number {
                     num_type: Primitives::FLOAT(32),
                     float: Some(self.tok.content.parse::<f64>().unwrap()),
@@ -457,6 +947,7 @@ impl Parser {
                 operand: None,
                 ident: None,
                 fnreturn: Box::new(None),
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(None),
@@ -464,11 +955,7 @@ impl Parser {
         } else if self.tok.content.contains(".") && self.tok.content.starts_with("-") == true {
             int = ParseTok {
                 tok_type: ParseType::NUMBER,
-                location: ParseLoc {
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
-                    line: self.tok.loc.line,
-                },
+                location: self.here(),
                 number: Some(Number {
                     num_type: Primitives::FLOAT(32),
                     float: Some(self.tok.content.parse::<f64>().unwrap()),
@@ -479,6 +966,7 @@ impl Parser {
                 operand: None,
                 fnreturn: Box::new(None),
                 ident: None,
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(None),
@@ -488,21 +976,14 @@ impl Parser {
         {
             int = ParseTok {
                 tok_type: ParseType::NUMBER,
-                location: ParseLoc {
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
-                    line: self.tok.loc.line,
-                },
-                number: Some(Number {
-                    num_type: Primitives::INT(32),
-                    number: Some(self.tok.content.parse::<i64>().unwrap()),
-                    float: None,
-                }),
+                location: self.here(),
+                number: Some(self.whole_literal()),
                 expression: None,
                 fnreturn: Box::new(None),
                 string: None,
                 operand: None,
                 ident: None,
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(None),
@@ -510,20 +991,13 @@ impl Parser {
         } else {
             int = ParseTok {
                 tok_type: ParseType::NUMBER,
-                location: ParseLoc {
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
-                    line: self.tok.loc.line,
-                },
-                number: Some(Number {
-                    num_type: Primitives::INT(32),
-                    number: Some(self.tok.content.parse::<i64>().unwrap()),
-                    float: None,
-                }),
+                location: self.here(),
+                number: Some(self.whole_literal()),
                 expression: None,
                 string: None,
                 operand: None,
                 ident: None,
+                test: Box::new(None),
                 variable: Box::new(None),
                 fnreturn: Box::new(None),
                 fncall: Box::new(None),
@@ -534,54 +1008,194 @@ impl Parser {
         return int;
     }
 
+    // Operators only work inside expressions, which are parsed as a whole by
+    // parse_exp, so one starting a statement or an argument is a mistake like
+    // `print! -x;` or a line still being typed.
     pub fn parse_operand(&mut self) -> ParseTok {
-        let op_type: BinOperand = match self.tok.tok_type {
-            TokenType::SUBBIN => BinOperand::SUB,
-            TokenType::PLUSBIN => BinOperand::PLUS,
-            TokenType::MULBIN => BinOperand::MUL,
-            TokenType::DIVBIN => BinOperand::DIV,
-            _ => unimplemented!(),
-        };
-        let tok = ParseTok {
-            tok_type: ParseType::OPERATOR,
-            location: ParseLoc {
-                start_col: self.tok.loc.col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
-            },
-            number: None,
-            expression: None,
-            string: None,
-            fnreturn: Box::new(None),
-            operand: Some(Operand { op_type }),
-            ident: None,
-            variable: Box::new(None),
-            fncall: Box::new(None),
-            fnmake: Box::new(None),
-        };
-        return tok;
+        diagnostic::fatal(
+            "Syntax Error",
+            self.tok.loc.line,
+            self.tok.loc.col,
+            format!(
+                "Unexpected {}, operators only work in expressions between parentheses",
+                self.tok.content
+            ),
+        );
     }
+}
+
+fn bin_operand(tok: &LexToken) -> BinOperand {
+    match tok.tok_type {
+        TokenType::SUBBIN => BinOperand::SUB,
+        TokenType::PLUSBIN => BinOperand::PLUS,
+        TokenType::MULBIN => BinOperand::MUL,
+        TokenType::DIVBIN => BinOperand::DIV,
+        TokenType::MODBIN => BinOperand::MOD,
+        TokenType::POWBIN => BinOperand::POW,
+        TokenType::BITAND => BinOperand::BITAND,
+        TokenType::BITOR => BinOperand::BITOR,
+        TokenType::BITXOR => BinOperand::BITXOR,
+        TokenType::BITNOT => BinOperand::BITNOT,
+        TokenType::SHL => BinOperand::SHL,
+        TokenType::SHR => BinOperand::SHR,
+        TokenType::NEG => BinOperand::NEG,
+        TokenType::NOT => BinOperand::NOT,
+        // A : where a value was expected, like `print! 1, :;`.
+        _ => diagnostic::fatal(
+            "Syntax Error",
+            tok.loc.line,
+            tok.loc.col,
+            format!("Unexpected {}", tok.content),
+        ),
+    }
+}
 
+// Whether a value of type `from` may be used where `to` is expected without
+// writing `as`: whole numbers grow into bigger ones or into floats.
+pub fn widens(from: &Primitives, to: &Primitives) -> bool {
+    match (from, to) {
+        (Primitives::INT(a) | Primitives::SIGINT(a), Primitives::INT(b)) => a < b,
+        (Primitives::INT(_) | Primitives::SIGINT(_), Primitives::FLOAT(_)) => true,
+        (Primitives::FLOAT(a), Primitives::FLOAT(b)) => a < b,
+        _ => false,
+    }
+}
+
+// Converts `value` to `target`, a std call of `as` that the generator turns
+// into the conversion.
+pub fn convert(value: ParseTok, target: Primitives, location: ParseLoc) -> ParseTok {
+    ParseTok {
+        tok_type: ParseType::FNCALL,
+        location,
+        expression: None,
+        number: None,
+        string: None,
+        operand: None,
+        fnreturn: Box::new(None),
+        ident: None,
+        test: Box::new(None),
+        variable: Box::new(None),
+        fncall: Box::new(Some(FnCall {
+            name: String::from("as"),
+            is_std: true,
+            args: vec![value],
+            ret_type: Some(target),
+        })),
+        fnmake: Box::new(None),
+    }
+}
+
+fn fits(number: i64, size: i8) -> bool {
+    let half = 1i128 << (size - 1);
+    (-half..half).contains(&(number as i128))
+}
+
+// Stops at a whole number literal that does not fit in the whole number type
+// it is used as.
+fn check_literal(value: &ParseTok, target: &Primitives) {
+    if let (
+        Some(Number {
+            number: Some(number),
+            ..
+        }),
+        Primitives::INT(size),
+    ) = (&value.number, target)
+    {
+        if value.tok_type == ParseType::NUMBER && !fits(*number, *size) {
+            diagnostic::fatal(
+                "Number out of range",
+                value.location.line,
+                value.location.col,
+                format!("{} does not fit in i{}", number, size),
+            );
+        }
+    }
+}
+
+// Whole number literals take the whole number type they are used as, so
+// `x: i8: 5` needs no conversion.
+fn fit_literal(value: ParseTok, target: &Primitives) -> ParseTok {
+    let mut value = value;
+    check_literal(&value, target);
+    if let (Some(number), Primitives::INT(_)) = (value.number.as_mut(), target) {
+        if value.tok_type == ParseType::NUMBER && number.number.is_some() {
+            number.num_type = target.clone();
+        }
+    }
+    value
+}
+
+// Grows a value used where `target` is expected, like an argument or a return
+// value, into that type when it widens. Anything else is left to the caller.
+pub fn widen(value: ParseTok, target: &Primitives) -> ParseTok {
+    let value = fit_literal(value, target);
+    if !widens(&get_prim(value.clone()), target) {
+        return value;
+    }
+    let location = value.location.clone();
+    convert(value, target.clone(), location)
+}
+
+// The 0 on the left of a prefix operator.
+fn zero(num_type: Primitives, op: &LexToken) -> ParseTok {
+    ParseTok {
+        tok_type: ParseType::NUMBER,
+        location: ParseLoc {
+            start_col: op.loc.start,
+            end_col: op.loc.end,
+            line: op.loc.line,
+            col: op.loc.col,
+        },
+        number: Some(Number {
+            num_type,
+            number: Some(0),
+            float: Some(0.0),
+        }),
+        expression: None,
+        string: None,
+        operand: None,
+        fnreturn: Box::new(None),
+        ident: None,
+        test: Box::new(None),
+        variable: Box::new(None),
+        fncall: Box::new(None),
+        fnmake: Box::new(None),
+    }
+}
+
+// Whether the tokens start with parentheses joining strings, (a ++ b).
+fn joins_strings(tokens: &[LexToken]) -> bool {
+    let mut depth = 0;
+    for tok in tokens {
+        match tok.tok_type {
+            TokenType::LPAREN => depth += 1,
+            TokenType::RPAREN => depth -= 1,
+            TokenType::CONCAT if depth == 1 => return true,
+            _ => {}
+        }
+        if depth == 0 {
+            break;
+        }
+    }
+    false
+}
+
+impl Parser {
     pub fn parse_ident(&mut self) -> ParseTok {
         let var = self.curr_scope.get(&self.tok.content);
         if var.is_none() == true {
-            println!(
-                "Undeclared variable ({line}:{col}): Cannot find variable {}",
-                self.tok.content,
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
+            diagnostic::fatal(
+                "Undeclared variable",
+                self.tok.loc.line,
+                self.tok.loc.col,
+                format!("Cannot find variable {}", self.tok.content),
             );
-            std::process::exit(1)
         }
 
         let var_data = var.unwrap();
         let tok = ParseTok {
             tok_type: ParseType::LABEL,
-            location: ParseLoc {
-                start_col: self.tok.loc.col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
-            },
+            location: self.here(),
             ident: Some(Label {
                 var_type: var_data.variable.clone().unwrap().value_type,
                 name: var_data.variable.clone().unwrap().name,
@@ -592,6 +1206,7 @@ impl Parser {
             string: None,
             fncall: Box::new(None),
             operand: None,
+            test: Box::new(None),
             variable: Box::new(None),
             fnmake: Box::new(None),
         };
@@ -599,155 +1214,71 @@ impl Parser {
         return tok;
     }
 
-    pub fn parse_rpn_exp(&mut self) -> ParseTok {
-        self.next_tok(); // eat RPN
-        self.next_tok(); // eat !
-
-        let mut exp_prim_type: Primitives = Primitives::INT(32);
-        let mut count = 0;
-
-        let mut output_tree: Vec<BinSeg> = vec![];
-        let mut working_stack = vec![];
-        while true == true {
-            let parse_out = Parser::new(
-                vec![self.tok.clone()],
-                self.file.to_owned(),
-                self.curr_scope.to_owned(),
-            )
-            .parse();
-            if count == 0 {
-                let whole_type = parse_out.tok_type.clone();
-                if whole_type == ParseType::NUMBER {
-                    exp_prim_type = parse_out.clone().number.unwrap().num_type;
-                } else if whole_type == ParseType::STRING {
-                    exp_prim_type = Primitives::STRING;
-                } else if whole_type == ParseType::LABEL {
-                    exp_prim_type = parse_out.ident.clone().unwrap().var_type;
-                }
-            }
-
-            let tok_prim = get_prim(parse_out.clone());
-
-            if prim_eq(&tok_prim, &exp_prim_type) == false && tok_prim != Primitives::OPERATOR {
-                println!(
-                    "Bad Types ({line},{col}): Cannot use type {:?} with type {:?}",
-                    tok_prim,
-                    exp_prim_type,
-                    line = self.tok.loc.line,
-                    col = self.tok.loc.col
-                );
-                std::process::exit(1);
-            }
-
-            if parse_out.tok_type == ParseType::NUMBER {
-                working_stack.push(parse_out.clone())
-            } else if parse_out.tok_type == ParseType::LABEL {
-                working_stack.push(parse_out.clone())
-            } else if tok_prim == Primitives::OPERATOR {
-                let left = working_stack.pop();
-                let right = working_stack.pop();
-                if prim_eq(&exp_prim_type, &Primitives::INT(32))
-                    || prim_eq(&exp_prim_type, &Primitives::SIGINT(32))
-                    || prim_eq(&exp_prim_type, &Primitives::FLOAT(32))
-                {
-                    if right.is_some() {
-                        output_tree.push(BinSeg {
-                            left: left.unwrap(),
-                            right: Some(right.unwrap()),
-                            operation: parse_out.operand.unwrap().op_type,
-                        })
-                    } else {
-                        output_tree.push(BinSeg {
-                            left: left.unwrap(),
-                            right: None,
-                            operation: parse_out.operand.unwrap().op_type,
-                        })
-                    }
-                }
-            } else {
-                unimplemented!()
-            }
-
-            if self.peek().tok_type == TokenType::EOF {
-                break;
-            };
-            count += 1;
-            self.next_tok()
-        }
-
-        return ParseTok {
-            tok_type: ParseType::EXP,
-            location: ParseLoc {
-                start_col: self.tok.loc.col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
-            },
-            expression: Some(Exp {
-                exp_type: exp_prim_type,
-                body: output_tree,
-            }),
-            number: None,
-            string: None,
-            operand: None,
-            fnreturn: Box::new(None),
-            ident: None,
-            variable: Box::new(None),
-            fncall: Box::new(None),
-            fnmake: Box::new(None),
-        };
-    }
     pub fn parse_var_def(&mut self) -> ParseTok {
-        let start_col = self.tok.loc.col;
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
+        let line = self.tok.loc.line;
         let name = self.tok.content.clone();
         self.next_tok(); // consume :
         if self.peek().tok_type != TokenType::LABEL {
-            println!(
-                "Unknown parser token ({line}:{col}): Expected token of type LABEL instead got {:?}",
-                self.peek().tok_type,
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
+            diagnostic::fatal(
+                "Unknown parser token",
+                self.tok.loc.line,
+                self.tok.loc.col,
+                format!(
+                    "Expected token of type LABEL instead got {:?}",
+                    self.peek().tok_type
+                ),
             );
-            std::process::exit(1);
         }
         self.next_tok();
         let var_type = Primitives::INSCOPE(self.tok.content.clone());
         if self.peek().tok_type != TokenType::COLON {
-            println!(
-                "Unknown parser token ({line}:{col}): Expected token : instead got {:?}",
-                self.peek().content,
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
+            diagnostic::fatal(
+                "Unknown parser token",
+                self.tok.loc.line,
+                self.tok.loc.col,
+                format!("Expected token : instead got {:?}", self.peek().content),
             );
-            std::process::exit(1);
         }
         //TODO: Add syntax for varName:int,int: tuple syntax
         self.next_tok();
         self.next_tok();
+        self.expect_more(line, col, format!("Variable {} has no value", name));
         let mut sub_tree = vec![];
         let curr_line = self.tok.loc.line;
         while self.tok.loc.line == curr_line {
             sub_tree.push(self.tok.clone());
             self.next_tok()
         }
-        let body = Parser::new(sub_tree, self.file.clone(), self.curr_scope.clone()).parse();
+        let end_col = sub_tree.last().unwrap().loc.end;
+        let first = sub_tree[0].clone();
+        let mut body = Parser::new(sub_tree, self.file.clone(), self.curr_scope.clone()).parse();
+        body = fit_literal(body, &keyword_types(var_type.clone()));
+        if widens(&get_prim(body.clone()), &keyword_types(var_type.clone())) {
+            body = convert(body, keyword_types(var_type.clone()), tok_loc(&first));
+        }
 
         if get_prim(body.clone()) != keyword_types(var_type.clone()) {
-            println!(
-                "Bad Types ({line}:{col}): Cannot assign type of {:?} with type {:?}",
-                get_prim(body.clone()),
-                keyword_types(var_type),
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
+            diagnostic::fatal(
+                "Bad Types",
+                line,
+                body.location.col,
+                format!(
+                    "Cannot assign type of {:?} with type {:?}",
+                    get_prim(body.clone()),
+                    keyword_types(var_type)
+                ),
             );
-            std::process::exit(1);
         }
 
         let ret_tok = ParseTok {
             tok_type: ParseType::VARDEF,
             location: ParseLoc {
                 start_col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
+                end_col,
+                line,
+                col,
             },
             expression: None,
             number: None,
@@ -757,6 +1288,7 @@ impl Parser {
             operand: None,
             ident: None,
             fncall: Box::new(None),
+            test: Box::new(None),
             variable: Box::new(Some(VarInit {
                 value_type: keyword_types(var_type),
                 name: name.to_string(),
@@ -767,12 +1299,12 @@ impl Parser {
         if body.clone().tok_type == ParseType::FNCALL
             && body.clone().fncall.unwrap().name == "print"
         {
-            println!(
-                "Unexpected tokens ({line}:{col}): Cannot assign print function call as variable.",
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
+            diagnostic::fatal(
+                "Unexpected tokens",
+                line,
+                col,
+                String::from("Cannot assign print function call as variable."),
             );
-            std::process::exit(1);
         }
         self.curr_scope.insert(name.to_string(), ret_tok.clone());
 
@@ -780,15 +1312,24 @@ impl Parser {
     }
 
     pub fn parse_func_call(&mut self) -> ParseTok {
-        let start_col = self.tok.loc.col;
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
+        let line = self.tok.loc.line;
         let name = self.tok.content.clone();
         self.next_tok();
         self.next_tok(); // consume !
         let mut sub_tree: Vec<Vec<LexToken>> = vec![];
         let mut temp_tree: Vec<LexToken> = vec![];
+        // Commas inside parentheses belong to a nested call.
+        let mut depth = 0;
         while self.tok.tok_type != TokenType::SEMCOLON && self.tok.tok_type != TokenType::EOF {
             temp_tree.push(self.tok.clone());
-            if self.tok.tok_type == TokenType::COMMA {
+            match self.tok.tok_type {
+                TokenType::LPAREN => depth += 1,
+                TokenType::RPAREN => depth -= 1,
+                _ => {}
+            }
+            if self.tok.tok_type == TokenType::COMMA && depth == 0 {
                 sub_tree.push(temp_tree.clone());
                 temp_tree.clear();
             }
@@ -806,6 +1347,17 @@ impl Parser {
                 self.curr_scope.to_owned(),
             );
             args.push(parsed_arg.parse());
+            if parsed_arg.tok.tok_type == TokenType::COMMA {
+                parsed_arg.next_tok();
+            }
+            if parsed_arg.tok.tok_type != TokenType::EOF {
+                diagnostic::fatal(
+                    "Syntax Error",
+                    parsed_arg.tok.loc.line,
+                    parsed_arg.tok.loc.col,
+                    format!("Expected , or ; instead got {}", parsed_arg.tok.content),
+                );
+            }
         }
         let fn_call = FnCall {
             args: args.clone(),
@@ -817,8 +1369,9 @@ impl Parser {
             tok_type: ParseType::FNCALL,
             location: ParseLoc {
                 start_col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
+                end_col: self.tok.loc.end,
+                line,
+                col,
             },
             expression: None,
             number: None,
@@ -826,6 +1379,7 @@ impl Parser {
             string: None,
             operand: None,
             ident: None,
+            test: Box::new(None),
             variable: Box::new(None),
             fncall: Box::new(Some(fn_call)),
             fnmake: Box::new(None),
@@ -834,82 +1388,26 @@ impl Parser {
             s.is_std = som_std::is_std_fn(&mut call);
             s
         }));
+        if call.fncall.clone().unwrap().is_std {
+            som_std::check_call(&mut call);
+        }
         self.next_tok();
-        if call.fncall.clone().unwrap().is_std == false {
-            if self.curr_scope.get(&name.to_string()).is_none()
-                || self.curr_scope.get(&name.to_string()).unwrap().tok_type != ParseType::FNMAKE
-            {
-                println!(
-                    "Undeclared function ({line}:{col}): Cannot call function {}",
-                    name.to_string(),
-                    line = self.tok.loc.line,
-                    col = self.tok.loc.col
-                );
-                std::process::exit(1)
-            }
-            let func = self
-                .curr_scope
-                .get(&name.to_string())
-                .unwrap()
-                .fnmake
-                .clone()
-                .unwrap();
-            if func.params.len() > sub_tree.clone().len() {
-                println!("Missing argument ({line}:{col}) Function call of {name} missing argument {arg}",
-                name=name,
-                arg=func.params.last().unwrap().clone().name,
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
-                )
-            }
-            if func.params.len() < sub_tree.clone().len() {
-                println!("Too many arguments ({line}:{col}) Function call of {name} expected {len} arguments",
-                name=name,
-                len=func.params.len(),
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
-                )
-            }
-            call.fncall = Box::new(call.fncall.clone().map(|mut s| {
-                s.ret_type = Some(
-                    self.curr_scope
-                        .get(&name.to_string())
-                        .unwrap()
-                        .clone()
-                        .fnmake
-                        .unwrap()
-                        .return_type,
-                );
-                s
-            }));
-            for parent_arg in func.params {
-                let mut arg: Option<ParseTok> = None;
-                for sup_arg in args.clone() {
-                    arg = Some(sup_arg.clone());
-                }
-                if let Some(a) = arg {
-                    if parent_arg.value_type != get_prim(a.to_owned()) {
-                        println!("Bad types ({line}:{col}): Argument {name} of type {:?} cannot be assigned to type {:?}",
-                        parent_arg.value_type,
-                        get_prim(a.clone()),
-                        name=parent_arg.name,
-                        line = self.tok.loc.line,
-                        col = self.tok.loc.col
-                    );
-                    }
-                }
-            }
+        if !call.fncall.clone().unwrap().is_std {
+            self.check_call(&mut call);
         }
         call
     }
     fn alt_parse_func_call(&mut self) -> ParseTok {
-        let start_col = self.tok.loc.col;
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
+        let line = self.tok.loc.line;
         self.next_tok(); // consume !
 
         let mut tok_tree: Vec<LexToken> = vec![];
         let mut count = 0;
 
         while true == true {
+            self.expect_more(line, col, String::from("Function call is missing )"));
             if self.tok.content == "(" {
                 tok_tree.push(self.tok.clone());
                 count = count + 1;
@@ -930,17 +1428,16 @@ impl Parser {
 
         let sub_tree: Vec<ParseTok> = vec![];
 
-        let mut parsed_arg = Parser::new(
-            tok_tree[1..].to_vec(),
-            self.file.to_owned(),
-            self.curr_scope.to_owned(),
-        );
-        parsed_arg.init();
-        let mut args: Vec<ParseTok> = parsed_arg.tree().clone();
-        for (i, arg) in args.clone().into_iter().enumerate() {
-            if arg.tok_type == ParseType::COMMA {
-                args.remove(i);
-            };
+        let mut args: Vec<ParseTok> = vec![];
+        if tok_tree.len() > 1 {
+            let mut parsed_arg = Parser::new(
+                tok_tree[1..].to_vec(),
+                self.file.to_owned(),
+                self.curr_scope.to_owned(),
+            );
+            parsed_arg.init();
+            args = parsed_arg.tree();
+            args.retain(|arg| arg.tok_type != ParseType::COMMA);
         }
 
         let fn_call = FnCall {
@@ -953,8 +1450,9 @@ impl Parser {
             tok_type: ParseType::FNCALL,
             location: ParseLoc {
                 start_col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
+                end_col: self.tok.loc.end,
+                line,
+                col,
             },
             expression: None,
             number: None,
@@ -962,6 +1460,7 @@ impl Parser {
             string: None,
             operand: None,
             ident: None,
+            test: Box::new(None),
             variable: Box::new(None),
             fncall: Box::new(Some(fn_call)),
             fnmake: Box::new(None),
@@ -970,100 +1469,178 @@ impl Parser {
             s.is_std = som_std::is_std_fn(&mut call);
             s
         }));
+        if call.fncall.clone().unwrap().is_std {
+            som_std::check_call(&mut call);
+        }
         self.next_tok();
-        if call.fncall.clone().unwrap().is_std == false {
-            if self.curr_scope.get(&name.to_string()).is_none()
-                || self.curr_scope.get(&name.to_string()).unwrap().tok_type != ParseType::FNMAKE
-            {
-                println!(
-                    "Undeclared function ({line}:{col}): Cannot call function {}",
-                    name.to_string(),
-                    line = self.tok.loc.line,
-                    col = self.tok.loc.col
-                );
-                std::process::exit(1)
-            }
-            let func = self
-                .curr_scope
-                .get(&name.to_string())
-                .unwrap()
-                .fnmake
-                .clone()
-                .unwrap();
-            if func.params.len() > args.clone().len() {
-                println!("Missing argument ({line}:{col}) Function call of {name} missing argument {arg}",
-                name=name,
-                arg=func.params.last().unwrap().clone().name,
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
-                )
-            }
-            if func.params.len() < args.clone().len() {
-                println!("Too many arguments ({line}:{col}) Function call of {name} expected {len} arguments",
-                name=name,
-                len=func.params.len(),
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
-                )
-            }
-            call.fncall = Box::new(call.fncall.clone().map(|mut s| {
-                s.ret_type = Some(
-                    self.curr_scope
-                        .get(&name.to_string())
-                        .unwrap()
-                        .clone()
-                        .fnmake
-                        .unwrap()
-                        .return_type,
+        if !call.fncall.clone().unwrap().is_std {
+            self.check_call(&mut call);
+        }
+        call
+    }
+    // Checks a call to a function of the program against its definition and
+    // fills in the type it gives back. Arguments grow into the types of their
+    // parameters the same way operands do.
+    fn check_call(&self, call: &mut ParseTok) {
+        let mut fncall = call.fncall.clone().unwrap();
+        let name = fncall.name.clone();
+        let func = match self.curr_scope.get(&name) {
+            Some(tok) if tok.tok_type == ParseType::FNMAKE => tok.fnmake.clone().unwrap(),
+            _ => diagnostic::fatal(
+                "Undeclared function",
+                call.location.line,
+                call.location.col,
+                format!("Cannot call function {}", name),
+            ),
+        };
+        if func.params.len() != fncall.args.len() {
+            diagnostic::fatal(
+                "Wrong number of arguments",
+                call.location.line,
+                call.location.col,
+                format!(
+                    "{name} takes {len} argument(s) instead got {got}",
+                    name = name,
+                    len = func.params.len(),
+                    got = fncall.args.len()
+                ),
+            );
+        }
+        for (i, param) in func.params.iter().enumerate() {
+            let arg = widen(fncall.args[i].clone(), &param.value_type);
+            let arg_type = get_prim(arg.clone());
+            if arg_type != param.value_type {
+                diagnostic::fatal(
+                    "Bad types",
+                    arg.location.line,
+                    arg.location.col,
+                    format!(
+                        "Argument {arg} of {name} must be of type {:?} instead got type {:?}",
+                        param.value_type,
+                        arg_type,
+                        arg = param.name,
+                        name = name
+                    ),
                 );
-                s
-            }));
-            for parent_arg in func.params {
-                let mut arg: Option<ParseTok> = None;
-                for sup_arg in args.clone() {
-                    arg = Some(sup_arg.clone());
-                }
-                if let Some(a) = arg {
-                    if parent_arg.value_type != get_prim(a.to_owned()) {
-                        println!("Bad types ({line}:{col}): Argument {name} of type {:?} cannot be assigned to type {:?}",
-                        parent_arg.value_type,
-                        get_prim(a.clone()),
-                        name=parent_arg.name,
-                        line = self.tok.loc.line,
-                        col = self.tok.loc.col
-                    );
-                    }
-                }
             }
+            fncall.args[i] = arg;
         }
-        call
+        fncall.ret_type = Some(func.return_type);
+        *call.fncall = Some(fncall);
     }
-    pub fn parse_string(&mut self) -> ParseTok {
-        let tok = ParseTok {
+    fn string_tok(&self, content: String, template: Vec<ParseTok>) -> ParseTok {
+        ParseTok {
             tok_type: ParseType::STRING,
             fnreturn: Box::new(None),
-            location: ParseLoc {
-                start_col: self.tok.loc.col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
-            },
+            location: self.here(),
             expression: None,
             number: None,
             string: Some(StringT {
-                content: self.tok.content.replace("\"", ""),
-                length: self.tok.content.replace("\"", "").len(),
+                length: content.len(),
+                content,
+                template,
             }),
             operand: None,
             ident: None,
+            test: Box::new(None),
             variable: Box::new(None),
             fncall: Box::new(None),
             fnmake: Box::new(None),
-        };
+        }
+    }
+
+    // Parses an expression interpolated into the current string token, `index`
+    // characters into it, with the variables in scope at the string.
+    fn parse_interpolation(&self, index: usize, code: String) -> ParseTok {
+        let base = loc_within(&self.tok.loc, &self.tok.content, index);
+        let mut lexer = Lexer::embedded(code.chars().collect(), base.clone());
+        lexer.lex();
+        if lexer.tree().is_empty() {
+            diagnostic::fatal(
+                "Bad interpolation",
+                base.line,
+                base.col,
+                String::from("Expected an expression between { and }"),
+            );
+        }
+        let mut parser = Parser::new(lexer.tree(), self.file.clone(), self.curr_scope.clone());
+        let value = parser.parse();
+        if parser.tok.tok_type != TokenType::EOF {
+            diagnostic::fatal(
+                "Bad interpolation",
+                parser.tok.loc.line,
+                parser.tok.loc.col,
+                format!("Expected a single value, found {}", parser.tok.content),
+            );
+        }
+        match value.tok_type {
+            ParseType::NUMBER | ParseType::STRING | ParseType::LABEL | ParseType::EXP => {}
+            ParseType::FNCALL
+                if !value.fncall.clone().unwrap().is_std
+                    || value.fncall.clone().unwrap().ret_type.is_some() => {}
+            ParseType::FNCALL => diagnostic::fatal(
+                "Bad type",
+                base.line,
+                base.col,
+                format!(
+                    "Function {} does not return a value",
+                    value.fncall.clone().unwrap().name
+                ),
+            ),
+            _ => diagnostic::fatal(
+                "Bad interpolation",
+                base.line,
+                base.col,
+                format!("Expected a value instead got {:?}", value.tok_type),
+            ),
+        }
+        match get_prim(value.clone()) {
+            Primitives::INT(_)
+            | Primitives::SIGINT(_)
+            | Primitives::FLOAT(_)
+            | Primitives::STRING => value,
+            other => diagnostic::fatal(
+                "Bad type",
+                base.line,
+                base.col,
+                format!("Cannot display type {:?} in a string", other),
+            ),
+        }
+    }
+
+    pub fn parse_string(&mut self) -> ParseTok {
+        let parts = string_parts(&self.tok.content);
+        let mut template = vec![];
+        if parts
+            .iter()
+            .any(|part| matches!(part, StringPart::Code(..)))
+        {
+            for part in parts {
+                template.push(match part {
+                    StringPart::Text(text) => self.string_tok(text, vec![]),
+                    StringPart::Code(index, code) => self.parse_interpolation(index, code),
+                });
+            }
+        }
+        let tok = self.string_tok(string_value(&self.tok.content), template);
         self.next_tok();
         tok
     }
     pub fn parse_func_def(&mut self) -> ParseTok {
         let name = self.tok.content.clone();
+        let line = self.tok.loc.line;
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
+        // Calls are resolved to std functions by name first, so a function with
+        // the same name could never be called.
+        if som_std::STD_FNS.contains(&name.as_str()) {
+            diagnostic::fatal(
+                "Reserved name",
+                line,
+                col,
+                format!("{} is a std function and cannot be defined again", name),
+            );
+        }
         let mut params = vec![];
         let mut count = 0;
         let mut temp = VarInit {
@@ -1073,6 +1650,11 @@ impl Parser {
         };
         self.next_tok();
         while self.tok.tok_type != TokenType::COLON {
+            self.expect_more(
+                line,
+                col,
+                format!("Function {} is missing a return type", name),
+            );
             if count == 0 && self.tok.content != "," {
                 temp.name = self.tok.clone().content;
             } else if count == 1 && self.tok.content != "," {
@@ -1095,14 +1677,25 @@ impl Parser {
         }
         self.next_tok(); // consume :
         let ret_type = keyword_types(Primitives::INSCOPE(String::from(self.tok.content.clone())));
+        // main runs after the top level statements and its result becomes the
+        // exit status of the program.
+        if name == "main" && (!params.is_empty() || ret_type != Primitives::INT(32)) {
+            diagnostic::fatal(
+                "Bad main",
+                line,
+                col,
+                String::from("main takes no parameters and returns an exit status of type i32"),
+            );
+        }
         self.curr_scope.insert(
             name.clone(),
             ParseTok {
                 tok_type: ParseType::FNMAKE,
                 location: ParseLoc {
-                    line: self.tok.loc.line,
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
+                    line,
+                    start_col,
+                    end_col: start_col + name.chars().count(),
+                    col,
                 },
                 expression: None,
                 number: None,
@@ -1110,6 +1703,7 @@ impl Parser {
                 string: None,
                 operand: None,
                 ident: None,
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(Some(FuncDef {
@@ -1128,9 +1722,10 @@ impl Parser {
             ParseTok {
                 tok_type: ParseType::FNMAKE,
                 location: ParseLoc {
-                    line: self.tok.loc.line,
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
+                    line,
+                    start_col,
+                    end_col: start_col + name.chars().count(),
+                    col,
                 },
                 expression: None,
                 number: None,
@@ -1138,6 +1733,7 @@ impl Parser {
                 operand: None,
                 fnreturn: Box::new(None),
                 ident: None,
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(Some(FuncDef {
@@ -1155,17 +1751,14 @@ impl Parser {
                 param.clone().name,
                 ParseTok {
                     tok_type: ParseType::FNPARAM,
-                    location: ParseLoc {
-                        start_col: self.tok.loc.col,
-                        end_col: self.tok.loc.end_col,
-                        line: self.tok.loc.line,
-                    },
+                    location: self.here(),
                     expression: None,
                     fnreturn: Box::new(None),
                     number: None,
                     string: None,
                     operand: None,
                     ident: None,
+                    test: Box::new(None),
                     variable: Box::new(Some(param.clone())),
                     fncall: Box::new(None),
                     fnmake: Box::new(None),
@@ -1177,9 +1770,11 @@ impl Parser {
         self.next_tok();
 
         while self.tok.content != "end" {
+            self.expect_more(line, col, format!("Function {} is missing end", name));
             sub_tree.push(self.tok.clone());
             self.next_tok()
         }
+        let end_col = self.tok.loc.end;
         self.next_tok(); // consume end keyword
 
         let mut body = Parser::new(sub_tree, self.file.clone(), func_scope);
@@ -1188,9 +1783,10 @@ impl Parser {
         let tok = ParseTok {
             tok_type: ParseType::FNMAKE,
             location: ParseLoc {
-                start_col: self.tok.loc.col,
-                end_col: self.tok.loc.end_col,
-                line: self.tok.loc.line,
+                start_col,
+                end_col,
+                line,
+                col,
             },
             expression: None,
             number: None,
@@ -1198,6 +1794,7 @@ impl Parser {
             operand: None,
             ident: None,
             fnreturn: Box::new(None),
+            test: Box::new(None),
             variable: Box::new(None),
             fncall: Box::new(None),
             fnmake: Box::new(Some(FuncDef {
@@ -1212,33 +1809,37 @@ impl Parser {
         tok
     }
     fn parse_ret(&mut self) -> ParseTok {
+        let line = self.tok.loc.line;
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
         self.next_tok(); // consume ret
         let mut sub_tree = vec![];
         while self.tok.content != ";" {
+            self.expect_more(line, col, String::from("Return is missing ;"));
             sub_tree.push(self.tok.clone());
             self.next_tok();
         }
+        let end_col = self.tok.loc.end;
         self.next_tok();
+        let func = self
+            .curr_scope
+            .get("self_ret!")
+            .unwrap()
+            .clone()
+            .fnmake
+            .unwrap();
         let val = Parser::new(sub_tree, self.file.clone(), self.curr_scope.clone()).parse();
+        let val = widen(val, &func.return_type);
 
         let val_type = get_prim(val.clone());
-        if prim_eq(
-            &self
-                .curr_scope
-                .get("self_ret!")
-                .unwrap()
-                .clone()
-                .fnmake
-                .unwrap()
-                .return_type,
-            &val_type,
-        ) {
+        if val_type == func.return_type {
             ParseTok {
                 tok_type: ParseType::FNRETURN,
                 location: ParseLoc {
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
-                    line: self.tok.loc.line,
+                    start_col,
+                    end_col,
+                    line,
+                    col,
                 },
                 expression: None,
                 number: None,
@@ -1246,19 +1847,75 @@ impl Parser {
                 operand: None,
                 ident: None,
                 fnreturn: Box::new(Some(FuncRet { value: Some(val) })),
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(None),
             }
         } else {
-            println!("Bad types ({line}:{col}): Return value of type {:?} for function {name} cannot be assigned to type {:?}",
-                        val_type,
-                        self.curr_scope.get("self_ret!").unwrap().clone().fnmake.unwrap().return_type,
-                        name=self.curr_scope.get("self_ret!").unwrap().clone().fnmake.unwrap().name,
-                        line = self.tok.loc.line,
-                        col = self.tok.loc.col
-                    );
-            std::process::exit(1);
+            diagnostic::fatal(
+                "Bad types",
+                line,
+                col,
+                format!(
+                    "Return value of type {:?} for function {name} cannot be assigned to type {:?}",
+                    val_type,
+                    func.return_type,
+                    name = func.name
+                ),
+            );
+        }
+    }
+
+    fn parse_test(&mut self) -> ParseTok {
+        let start_col = self.tok.loc.start;
+        let col = self.tok.loc.col;
+        let line = self.tok.loc.line;
+        self.next_tok(); // consume test
+        let name = string_value(&self.tok.content);
+        self.next_tok();
+
+        let mut sub_tree: Vec<LexToken> = vec![];
+        while self.tok.content != "end" {
+            if self.tok.tok_type == TokenType::EOF {
+                diagnostic::fatal(
+                    "Unexpected end of file",
+                    line,
+                    col,
+                    format!("Test {} is missing end", name),
+                );
+            }
+            sub_tree.push(self.tok.clone());
+            self.next_tok()
+        }
+        let end_col = self.tok.loc.end;
+        self.next_tok(); // consume end keyword
+
+        let mut body = vec![];
+        if !sub_tree.is_empty() {
+            let mut parser = Parser::new(sub_tree, self.file.clone(), self.curr_scope.clone());
+            parser.init();
+            body = parser.tree();
+        }
+
+        ParseTok {
+            tok_type: ParseType::TEST,
+            location: ParseLoc {
+                start_col,
+                end_col,
+                line,
+                col,
+            },
+            expression: None,
+            number: None,
+            string: None,
+            operand: None,
+            ident: None,
+            fnreturn: Box::new(None),
+            test: Box::new(Some(TestBlock { name, body })),
+            variable: Box::new(None),
+            fncall: Box::new(None),
+            fnmake: Box::new(None),
         }
     }
 
@@ -1270,11 +1927,33 @@ impl Parser {
         self.file
     }
 
+    // Every node starts at its first token, which is how the formatter splits
+    // a body back into statements.
     pub fn parse(&mut self) -> ParseTok {
-        if self.tok.tok_type == TokenType::LPAREN {
+        let start_col = self.tok.loc.start;
+        let line = self.tok.loc.line;
+        let col = self.tok.loc.col;
+        let mut tok = self.parse_node();
+        tok.location.start_col = start_col;
+        tok.location.line = line;
+        tok.location.col = col;
+        tok
+    }
+
+    fn parse_node(&mut self) -> ParseTok {
+        if self.tok.content == "test" && self.peek().tok_type == TokenType::STRING {
+            self.parse_test()
+        } else if self.tok.tok_type == TokenType::LPAREN {
             self.parse_exp()
-        } else if self.tok.content == "RPN" && self.peek().tok_type == TokenType::MMARK {
-            self.parse_rpn_exp()
+        } else if self.tok.content == "ret" {
+            self.parse_ret()
+        } else if self.tok.tok_type == TokenType::LABEL
+            && self.peek().content == ":"
+            && self.peek_at(3).content != ":"
+            && self.peek_at(3).line() > self.peek_at(2).line()
+        {
+            // A function without parameters, `main: i32` with the body below.
+            self.parse_func_def()
         } else if self.tok.tok_type == TokenType::LABEL && self.peek().content == ":" {
             self.parse_var_def()
         } else if self.tok.tok_type == TokenType::NUMBER
@@ -1290,14 +1969,12 @@ impl Parser {
         } else if self.tok.tok_type == TokenType::LABEL && self.peek().tok_type == TokenType::LABEL
         {
             self.parse_func_def()
-        } else if self.tok.content == "ret" {
-            self.parse_ret()
         } else if self.tok.tok_type == TokenType::LABEL {
             self.parse_ident()
-        } else if self.tok.tok_type == TokenType::PLUSBIN
-            || self.tok.tok_type == TokenType::SUBBIN
-            || self.tok.tok_type == TokenType::MULBIN
-            || self.tok.tok_type == TokenType::DIVBIN
+        } else if is_bin_op(&self.tok.tok_type)
+            || self.tok.tok_type == TokenType::BITNOT
+            || self.tok.tok_type == TokenType::NEG
+            || self.tok.tok_type == TokenType::NOT
             || self.tok.tok_type == TokenType::COLON
         {
             self.parse_operand()
@@ -1307,30 +1984,25 @@ impl Parser {
             self.next_tok();
             ParseTok {
                 tok_type: ParseType::COMMA,
-                location: ParseLoc {
-                    start_col: self.tok.loc.col,
-                    end_col: self.tok.loc.end_col,
-                    line: self.tok.loc.line,
-                },
+                location: self.here(),
                 expression: None,
                 number: None,
                 string: None,
                 operand: None,
                 ident: None,
+                test: Box::new(None),
                 variable: Box::new(None),
                 fncall: Box::new(None),
                 fnmake: Box::new(None),
                 fnreturn: Box::new(None),
             }
         } else {
-            println!(
-                "Unknown parser token ({line},{col}): {}",
-                self.tok.content,
-                line = self.tok.loc.line,
-                col = self.tok.loc.col
+            diagnostic::fatal(
+                "Unknown parser token",
+                self.tok.loc.line,
+                self.tok.loc.col,
+                format!("{} of type {:?}", self.tok.content, self.tok.tok_type),
             );
-            println!("Of type: {:?}", self.tok.tok_type);
-            std::process::exit(1);
         }
     }
     pub fn init(&mut self) {
@@ -0,0 +1,145 @@
+use crate::parse::{BinSeg, ParseTok, ParseType, Primitives};
+
+// Renders parse trees as indented s-expressions for `--emit=ast`. Every node is
+// annotated with the type the parser inferred for it.
+
+pub fn prim_name(prim: &Primitives) -> String {
+    match prim {
+        Primitives::INT(size) => format!("i{}", size),
+        Primitives::SIGINT(size) => format!("si{}", size),
+        Primitives::FLOAT(size) => format!("f{}", size),
+        Primitives::STRING => String::from("str"),
+        Primitives::INSCOPE(name) => name.clone(),
+        _ => format!("{:?}", prim).to_lowercase(),
+    }
+}
+
+pub fn print_tree(tree: &[ParseTok]) -> String {
+    let mut out = String::new();
+    for tok in tree {
+        print_tok(tok, 0, &mut out);
+    }
+    out
+}
+
+fn line(depth: usize, tok: &ParseTok, content: String, out: &mut String) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(&content);
+    out.push_str(&format!(" @{}:{}\n", tok.location.line, tok.location.col));
+}
+
+fn print_seg(seg: &BinSeg, depth: usize, out: &mut String) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(&format!("{:?}\n", seg.operation));
+    print_tok(&seg.left, depth + 1, out);
+    print_tok(&seg.right, depth + 1, out);
+}
+
+fn print_tok(tok: &ParseTok, depth: usize, out: &mut String) {
+    match tok.tok_type {
+        ParseType::VARDEF => {
+            let var = tok.variable.as_ref().as_ref().unwrap();
+            line(
+                depth,
+                tok,
+                format!("VarDef {}: {}", var.name, prim_name(&var.value_type)),
+                out,
+            );
+            if let Some(value) = &var.value {
+                print_tok(value, depth + 1, out);
+            }
+        }
+        ParseType::FNMAKE => {
+            let func = tok.fnmake.as_ref().as_ref().unwrap();
+            let params: Vec<String> = func
+                .params
+                .iter()
+                .map(|p| format!("{} {}", p.name, prim_name(&p.value_type)))
+                .collect();
+            line(
+                depth,
+                tok,
+                format!(
+                    "FuncDef {}({}): {}",
+                    func.name,
+                    params.join(", "),
+                    prim_name(&func.return_type)
+                ),
+                out,
+            );
+            for statement in &func.body {
+                print_tok(statement, depth + 1, out);
+            }
+        }
+        ParseType::FNCALL => {
+            let call = tok.fncall.as_ref().as_ref().unwrap();
+            let ret = match &call.ret_type {
+                Some(ret) => prim_name(ret),
+                None => String::from("?"),
+            };
+            let kind = if call.is_std { "StdCall" } else { "Call" };
+            line(depth, tok, format!("{} {}: {}", kind, call.name, ret), out);
+            for arg in &call.args {
+                print_tok(arg, depth + 1, out);
+            }
+        }
+        ParseType::FNRETURN => {
+            line(depth, tok, String::from("Return"), out);
+            if let Some(value) = &tok.fnreturn.as_ref().as_ref().unwrap().value {
+                print_tok(value, depth + 1, out);
+            }
+        }
+        ParseType::EXP => {
+            let exp = tok.expression.as_ref().unwrap();
+            line(
+                depth,
+                tok,
+                format!("Exp: {}", prim_name(&exp.exp_type)),
+                out,
+            );
+            print_seg(&exp.body, depth + 1, out);
+        }
+        ParseType::NUMBER => {
+            let num = tok.number.as_ref().unwrap();
+            let value = match (num.number, num.float) {
+                (Some(int), _) => int.to_string(),
+                (None, Some(float)) => float.to_string(),
+                (None, None) => String::from("?"),
+            };
+            line(
+                depth,
+                tok,
+                format!("Number {}: {}", value, prim_name(&num.num_type)),
+                out,
+            );
+        }
+        ParseType::STRING => {
+            let string = tok.string.as_ref().unwrap();
+            line(depth, tok, format!("String {:?}: str", string.content), out);
+            for part in &string.template {
+                print_tok(part, depth + 1, out);
+            }
+        }
+        ParseType::LABEL => {
+            let ident = tok.ident.as_ref().unwrap();
+            line(
+                depth,
+                tok,
+                format!("Label {}: {}", ident.name, prim_name(&ident.var_type)),
+                out,
+            );
+        }
+        ParseType::OPERATOR => {
+            let op = tok.operand.as_ref().unwrap();
+            line(depth, tok, format!("Operator {:?}", op.op_type), out);
+        }
+        ParseType::TEST => {
+            let test = tok.test.as_ref().as_ref().unwrap();
+            line(depth, tok, format!("Test {:?}", test.name), out);
+            for statement in &test.body {
+                print_tok(statement, depth + 1, out);
+            }
+        }
+        _ => line(depth, tok, format!("{:?}", tok.tok_type), out),
+    }
+}
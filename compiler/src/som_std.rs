@@ -1,12 +1,243 @@
+use crate::diagnostic;
 use crate::parse;
 
-//TODO: Add string templating
-// pub fn str_template() {}
+pub const STD_FNS: [&str; 50] = [
+    "print",
+    "eprint",
+    "include",
+    "assert",
+    "assert_eq",
+    "len",
+    "concat",
+    "substring",
+    "split",
+    "join",
+    "trim",
+    "contains",
+    "replace",
+    "to_upper",
+    "to_lower",
+    "parse_int",
+    "parse_float",
+    "to_string",
+    "read_line",
+    "read_all",
+    "read_file",
+    "write_file",
+    "append_file",
+    "io_error",
+    "arg",
+    "arg_count",
+    "env",
+    "exit",
+    "panic",
+    "abs",
+    "min",
+    "max",
+    "clamp",
+    "pow",
+    "sqrt",
+    "floor",
+    "ceil",
+    "round",
+    "sin",
+    "cos",
+    "tan",
+    "asin",
+    "acos",
+    "atan",
+    "atan2",
+    "exp",
+    "ln",
+    "log10",
+    "pi",
+    "e",
+];
 
 pub fn is_std_fn(fn_call: &mut parse::ParseTok) -> bool {
-    match fn_call.clone().fncall.unwrap().name.as_str() {
-        "print" => true,
-        "include" => true,
-        _ => false,
+    STD_FNS.contains(&fn_call.clone().fncall.unwrap().name.as_str())
+}
+
+// Parameter types of the std functions and the type of the value they give
+// back, "none" when they only do something. "num" takes any number and ".."
+// repeats the parameter before it, a "num" return is the type of the first
+// argument and every "num" argument must then have that type. A "float" return
+// is f64 when any argument is an f64 and f32 otherwise. "any" takes a value of
+// any type as long as every "any" argument has the same one. None for functions
+// taking anything, like print.
+fn signature(name: &str) -> Option<(Vec<&'static str>, &'static str)> {
+    let (params, ret) = match name {
+        "len" => (vec!["str"], "i32"),
+        "concat" => (vec!["str", "str"], "str"),
+        "substring" => (vec!["str", "i32", "i32"], "str"),
+        "split" => (vec!["str", "str", "i32"], "str"),
+        "join" => (vec!["str", "str", ".."], "str"),
+        "trim" | "to_upper" | "to_lower" => (vec!["str"], "str"),
+        "contains" => (vec!["str", "str"], "i32"),
+        "replace" => (vec!["str", "str", "str"], "str"),
+        "parse_int" => (vec!["str"], "i32"),
+        "parse_float" => (vec!["str"], "f32"),
+        "to_string" => (vec!["num"], "str"),
+        "read_line" | "read_all" | "io_error" => (vec![], "str"),
+        "read_file" => (vec!["str"], "str"),
+        "write_file" | "append_file" => (vec!["str", "str"], "i32"),
+        // Without lists the arguments are read one at a time instead of as an
+        // args list.
+        "arg" => (vec!["i32"], "str"),
+        "arg_count" => (vec![], "i32"),
+        "env" => (vec!["str"], "str"),
+        "exit" => (vec!["i32"], "none"),
+        "panic" => (vec!["str"], "none"),
+        "abs" => (vec!["num"], "num"),
+        "min" | "max" | "pow" => (vec!["num", "num"], "num"),
+        "clamp" => (vec!["num", "num", "num"], "num"),
+        "sqrt" | "floor" | "ceil" | "round" | "sin" | "cos" | "tan" | "asin" | "acos" | "atan"
+        | "exp" | "ln" | "log10" => (vec!["num"], "float"),
+        "atan2" => (vec!["num", "num"], "float"),
+        // Only as precise as an f32, an f64 result would not fit the f32
+        // variables they are mostly used with.
+        "pi" | "e" => (vec![], "f32"),
+        "assert" => (vec!["num"], "none"),
+        "assert_eq" => (vec!["any", "any"], "none"),
+        _ => return None,
+    };
+    Some((params, ret))
+}
+
+// Signature shown by editors, `len(str): i32`.
+pub fn std_detail(name: &str) -> Option<String> {
+    signature(name).map(|(params, ret)| match ret {
+        "none" => format!("{}({})", name, params.join(", ")),
+        "float" => format!("{}({}): f32 or f64", name, params.join(", ")),
+        _ => format!("{}({}): {}", name, params.join(", "), ret),
+    })
+}
+
+fn accepts(param: &str, arg: &parse::Primitives) -> bool {
+    match param {
+        "num" => matches!(
+            arg,
+            parse::Primitives::INT(_) | parse::Primitives::SIGINT(_) | parse::Primitives::FLOAT(_)
+        ),
+        "any" => true,
+        _ => &parse::keyword_types(parse::Primitives::INSCOPE(param.to_string())) == arg,
+    }
+}
+
+// Checks the arguments of a call to a std function and fills in the type of
+// the value it gives back.
+pub fn check_call(call: &mut parse::ParseTok) {
+    let mut fncall = call.fncall.clone().unwrap();
+    let (params, ret) = match signature(&fncall.name) {
+        Some(signature) => signature,
+        None => return,
+    };
+    let line = call.location.line;
+    let col = call.location.col;
+    let variadic = params.last() == Some(&"..");
+    let fixed = if variadic {
+        params.len() - 1
+    } else {
+        params.len()
+    };
+    if fncall.args.len() < fixed || (!variadic && fncall.args.len() > fixed) {
+        diagnostic::fatal(
+            "Wrong number of arguments",
+            line,
+            col,
+            format!(
+                "{name} takes {more}{fixed} argument(s) instead got {len}",
+                name = fncall.name,
+                more = if variadic { "at least " } else { "" },
+                fixed = fixed,
+                len = fncall.args.len()
+            ),
+        );
+    }
+    for i in 0..fncall.args.len() {
+        let param = params[i.min(fixed - 1)];
+        if param != "num" && param != "any" {
+            // Smaller whole numbers grow into the i32 parameters.
+            let target = parse::keyword_types(parse::Primitives::INSCOPE(param.to_string()));
+            fncall.args[i] = parse::widen(fncall.args[i].clone(), &target);
+        }
+        let arg = &fncall.args[i];
+        if arg.tok_type == parse::ParseType::FNCALL
+            && arg.fncall.clone().unwrap().ret_type.is_none()
+        {
+            diagnostic::fatal(
+                "Bad type",
+                arg.location.line,
+                arg.location.col,
+                format!(
+                    "Function {} does not return a value",
+                    arg.fncall.clone().unwrap().name
+                ),
+            );
+        }
+        let arg_type = parse::get_prim(arg.clone());
+        if !accepts(param, &arg_type) {
+            diagnostic::fatal(
+                "Bad types",
+                arg.location.line,
+                arg.location.col,
+                format!(
+                    "Argument {n} of {name} must be {expected} instead got type {:?}",
+                    arg_type,
+                    n = i + 1,
+                    name = fncall.name,
+                    expected = match param {
+                        "num" => String::from("a number"),
+                        _ => format!("of type {}", param),
+                    }
+                ),
+            );
+        }
+    }
+    if params.first() == Some(&"any") {
+        let first = parse::get_prim(fncall.args[0].clone());
+        for arg in fncall.args.iter().skip(1) {
+            let arg_type = parse::get_prim(arg.clone());
+            if !parse::prim_eq(&first, &arg_type) {
+                diagnostic::fatal(
+                    "Bad types",
+                    line,
+                    col,
+                    format!("Cannot compare type {:?} with type {:?}", first, arg_type),
+                );
+            }
+        }
+    }
+    if ret == "num" {
+        let first = parse::get_prim(fncall.args[0].clone());
+        for (i, arg) in fncall.args.iter().enumerate().skip(1) {
+            let arg_type = parse::get_prim(arg.clone());
+            if params[i.min(fixed - 1)] == "num" && arg_type != first {
+                diagnostic::fatal(
+                    "Bad types",
+                    arg.location.line,
+                    arg.location.col,
+                    format!(
+                        "Argument {n} of {name} must be of type {:?} like argument 1 instead got type {:?}",
+                        first,
+                        arg_type,
+                        n = i + 1,
+                        name = fncall.name
+                    ),
+                );
+            }
+        }
+        fncall.ret_type = Some(first);
+    } else if ret == "float" {
+        let wide = fncall
+            .args
+            .iter()
+            .any(|arg| parse::get_prim(arg.clone()) == parse::Primitives::FLOAT(64));
+        fncall.ret_type = Some(parse::Primitives::FLOAT(if wide { 64 } else { 32 }));
+    } else if ret != "none" {
+        fncall.ret_type = Some(parse::keyword_types(parse::Primitives::INSCOPE(
+            ret.to_string(),
+        )));
     }
+    *call.fncall = Some(fncall);
 }
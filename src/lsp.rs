@@ -0,0 +1,399 @@
+use compiler::analysis::{self, Analysis, Symbol, SymbolKind};
+use compiler::diagnostic::Severity;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+// A language server over stdio speaking JSON-RPC with Content-Length framing.
+// Documents are synced whole and re-analysed on every change.
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+    // The last analysis that parsed, used for completion while the user is
+    // halfway through typing something.
+    last_complete: Analysis,
+}
+
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut out = io::stdout().lock();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    out.flush().unwrap();
+}
+
+// LSP positions count UTF-16 code units on a line, the analysis counts
+// characters from the start of the file.
+fn position(text: &str, offset: usize) -> Value {
+    let mut line = 0;
+    let mut character = 0;
+    for ch in text.chars().take(offset) {
+        if ch == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += ch.len_utf16();
+        }
+    }
+    json!({ "line": line, "character": character })
+}
+
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let mut offset = 0;
+    let mut lines = text.split('\n');
+    for _ in 0..line {
+        match lines.next() {
+            Some(skipped) => offset += skipped.chars().count() + 1,
+            None => return offset,
+        }
+    }
+    let mut units = 0;
+    for ch in lines.next().unwrap_or("").chars() {
+        if units >= character {
+            break;
+        }
+        units += ch.len_utf16();
+        offset += 1;
+    }
+    offset
+}
+
+// Compiler diagnostics count lines and columns from 1.
+fn line_col_offset(text: &str, line: u32, col: usize) -> usize {
+    let line_start: usize = text
+        .split('\n')
+        .take(line.saturating_sub(1) as usize)
+        .map(|line| line.chars().count() + 1)
+        .sum();
+    (line_start + col.saturating_sub(1)).min(text.chars().count())
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": 1,
+            "definitionProvider": true,
+            "hoverProvider": true,
+            "documentSymbolProvider": true,
+            "completionProvider": {}
+        },
+        "serverInfo": { "name": "something", "version": clap::crate_version!() }
+    })
+}
+
+fn publish_diagnostics(uri: &str, doc: &Document) {
+    let diagnostics: Vec<Value> = doc
+        .analysis
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let start = line_col_offset(&doc.text, diagnostic.line, diagnostic.col);
+            let end = match doc.analysis.word_at(start) {
+                Some((_, end, _)) => end,
+                None => start + 1,
+            };
+            json!({
+                "range": range(&doc.text, start, end),
+                "severity": if diagnostic.severity == Severity::Error { 1 } else { 2 },
+                "source": "something",
+                "message": format!("{}: {}", diagnostic.kind, diagnostic.message),
+            })
+        })
+        .collect();
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    }));
+}
+
+fn document_symbol(text: &str, symbol: &Symbol) -> Value {
+    let kind = match symbol.kind {
+        SymbolKind::Function => 12,
+        SymbolKind::Variable | SymbolKind::Parameter => 13,
+        SymbolKind::Test => 6,
+    };
+    let children: Vec<Value> = symbol
+        .children
+        .iter()
+        .map(|child| document_symbol(text, child))
+        .collect();
+    json!({
+        "name": symbol.name,
+        "detail": symbol.detail,
+        "kind": kind,
+        "range": range(text, symbol.range.0, symbol.range.1),
+        "selectionRange": range(text, symbol.start, symbol.end),
+        "children": children,
+    })
+}
+
+fn hover(doc: &Document, at: usize) -> Value {
+    let (start, end, name) = match doc.analysis.word_at(at) {
+        Some(word) => word,
+        None => return Value::Null,
+    };
+    let detail = match doc.analysis.definition(at) {
+        Some(symbol) => symbol.detail.clone(),
+        None if analysis::STD_FNS.contains(&name) => match analysis::std_detail(name) {
+            Some(signature) => format!("{} (built-in)", signature),
+            None => format!("{}! (built-in)", name),
+        },
+        None => return Value::Null,
+    };
+    json!({
+        "contents": { "kind": "markdown", "value": format!("```something\n{}\n```", detail) },
+        "range": range(&doc.text, start, end),
+    })
+}
+
+fn completion(doc: &Document, at: usize) -> Value {
+    let analysis = if doc.analysis.complete {
+        &doc.analysis
+    } else {
+        &doc.last_complete
+    };
+    let mut items: Vec<Value> = analysis
+        .in_scope(at)
+        .into_iter()
+        .map(|symbol| {
+            let kind = if symbol.kind == SymbolKind::Function {
+                3
+            } else {
+                6
+            };
+            json!({ "label": symbol.name, "kind": kind, "detail": symbol.detail })
+        })
+        .collect();
+    for name in analysis::STD_FNS {
+        let detail = analysis::std_detail(name).unwrap_or_else(|| String::from("built-in"));
+        items.push(json!({ "label": name, "kind": 3, "detail": detail }));
+    }
+    for name in analysis::KEYWORDS.iter().chain(analysis::TYPES.iter()) {
+        items.push(json!({ "label": name, "kind": 14 }));
+    }
+    Value::Array(items)
+}
+
+// Answers a request, None when the method is unknown.
+fn respond(documents: &HashMap<String, Document>, method: &str, params: &Value) -> Option<Value> {
+    if method == "initialize" {
+        return Some(capabilities());
+    }
+    if method == "shutdown" {
+        return Some(Value::Null);
+    }
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+    let doc = match documents.get(uri) {
+        Some(doc) => doc,
+        None => return Some(Value::Null),
+    };
+    let at = offset(&doc.text, &params["position"]);
+    match method {
+        "textDocument/definition" => Some(match doc.analysis.definition(at) {
+            Some(symbol) => {
+                json!({ "uri": uri, "range": range(&doc.text, symbol.start, symbol.end) })
+            }
+            None => Value::Null,
+        }),
+        "textDocument/hover" => Some(hover(doc, at)),
+        "textDocument/documentSymbol" => Some(Value::Array(
+            doc.analysis
+                .symbols
+                .iter()
+                .map(|symbol| document_symbol(&doc.text, symbol))
+                .collect(),
+        )),
+        "textDocument/completion" => Some(completion(doc, at)),
+        _ => None,
+    }
+}
+
+fn update(documents: &mut HashMap<String, Document>, uri: &str, text: String) {
+    let analysis = analysis::analyse(&text);
+    let last_complete = match documents.remove(uri) {
+        Some(_) if analysis.complete => analysis.clone(),
+        Some(previous) => previous.last_complete,
+        None => analysis.clone(),
+    };
+    let doc = Document {
+        text,
+        analysis,
+        last_complete,
+    };
+    publish_diagnostics(uri, &doc);
+    documents.insert(uri.to_string(), doc);
+}
+
+// Serves until the client sends exit, returning the process exit code.
+pub fn serve() -> i32 {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    let mut shutdown = false;
+
+    while let Some(message) = read_message(&mut input) {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        match method {
+            "exit" => return if shutdown { 0 } else { 1 },
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                let text = doc["text"].as_str().unwrap_or("").to_string();
+                update(&mut documents, doc["uri"].as_str().unwrap_or(""), text);
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let changes = params["contentChanges"].as_array();
+                if let Some(change) = changes.and_then(|changes| changes.last()) {
+                    let text = change["text"].as_str().unwrap_or("").to_string();
+                    update(&mut documents, uri, text);
+                }
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                documents.remove(uri);
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                }));
+            }
+            _ => {
+                // Notifications without an id need no answer.
+                let id = match message.get("id") {
+                    Some(id) => id.clone(),
+                    None => continue,
+                };
+                if method == "shutdown" {
+                    shutdown = true;
+                }
+                let reply = match respond(&documents, method, params) {
+                    Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    None => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("Unknown method {}", method) },
+                    }),
+                };
+                send(reply);
+            }
+        }
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor, Read};
+
+    fn framed(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    // Gives out a few bytes per read, like a pipe that has only received part
+    // of a message so far.
+    struct Trickle {
+        data: Vec<u8>,
+        at: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3).min(self.data.len() - self.at);
+            buf[..n].copy_from_slice(&self.data[self.at..self.at + n]);
+            self.at += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reads_a_message() {
+        let mut input = Cursor::new(framed(r#"{"method":"initialized"}"#));
+        assert_eq!(read_message(&mut input).unwrap()["method"], "initialized");
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn reads_messages_sent_together() {
+        let text = framed(r#"{"id":1}"#) + &framed(r#"{"id":2}"#) + &framed(r#"{"id":3}"#);
+        let mut input = Cursor::new(text);
+        for id in 1..=3 {
+            assert_eq!(read_message(&mut input).unwrap()["id"], id);
+        }
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn reads_messages_arriving_in_pieces() {
+        let text = "Content-Type: application/vscode-jsonrpc\r\n".to_string()
+            + &framed(r#"{"method":"exit"}"#);
+        let mut input = BufReader::with_capacity(
+            4,
+            Trickle {
+                data: text.into_bytes(),
+                at: 0,
+            },
+        );
+        assert_eq!(read_message(&mut input).unwrap()["method"], "exit");
+    }
+
+    #[test]
+    fn stops_at_a_partial_header() {
+        let mut input = Cursor::new("Content-Len");
+        assert!(read_message(&mut input).is_none());
+        let mut input = Cursor::new("Content-Length: 20\r\n");
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn stops_without_a_length() {
+        let mut input = Cursor::new("Content-Type: application/vscode-jsonrpc\r\n\r\n{}");
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn stops_at_a_short_body() {
+        let mut input = Cursor::new("Content-Length: 20\r\n\r\n{\"id\":1}");
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn converts_positions() {
+        let text = "a: str: \"é😀\"\nb";
+        let end = text.chars().count();
+        assert_eq!(position(text, end), json!({ "line": 1, "character": 1 }));
+        let quote = text.find('"').unwrap() + 1;
+        let after_emoji = json!({ "line": 0, "character": quote + 3 });
+        assert_eq!(offset(text, &after_emoji), quote + 2);
+        assert_eq!(position(text, quote + 2), after_emoji);
+        assert_eq!(line_col_offset(text, 2, 1), end - 1);
+    }
+}
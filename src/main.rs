@@ -1,116 +1,467 @@
-use clap::{App, Arg};
-use compiler;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use indexmap::IndexMap;
 use std::collections::HashMap;
-use std::env;
 use std::fs;
-use utils;
+use std::path::{Path, PathBuf};
 
 use compiler::*;
-fn main() {
-    let app = App::new("Something")
-        .author("Ashtyn")
-        .version(clap::crate_version!())
-        .about("A functional programming language.");
 
-    let file = app
-        .arg(
-            Arg::with_name("file_name")
-                .required(true)
-                .help("input file filename"),
-        )
-        .arg(
-            Arg::with_name("dev-mode")
-                .short("d")
-                .long("dev")
-                .takes_value(false)
-                .help("Prevents work directory clean up."),
-        )
-        .arg(
-            Arg::with_name("gen")
-                .short("g")
-                .long("gen")
-                .takes_value(false)
-                .help("Stops compiler at generation step (this also stops cleaning of work directory)."),
-        ).arg(Arg::with_name("run").short("r").long("run").takes_value(true).help("Automatically runs generated executable."))
-        .get_matches();
-
-    let f = fs::read_to_string(file.value_of("file_name").unwrap());
-
-    if f.is_err() {
-        println!(
-            "Could not read file {}",
-            file.value_of("file_name").unwrap()
-        )
-    } else {
-        utils::clean_work();
-        let file_content = f.unwrap();
-        let mut lexer = Lexer::new(file_content.chars().collect());
+mod lsp;
+
+// Exit codes shared by every subcommand. Lexer and parser errors already exit with
+// EXIT_COMPILE from inside the compiler crate.
+const EXIT_COMPILE: i32 = 1;
+const EXIT_IO: i32 = 2;
+const EXIT_BUILD: i32 = 3;
+
+struct Generated {
+    module: String,
+    lib: String,
+}
+
+impl Generated {
+    // The module with the som_std.cc library inlined in place of its include.
+    fn translation_unit(&self) -> String {
+        self.module
+            .replacen("#include \"som_std.cc\"", &self.lib, 1)
+    }
+}
+
+fn file_arg() -> Arg<'static, 'static> {
+    Arg::with_name("file_name")
+        .required(true)
+        .help("input file filename")
+}
+
+fn dev_arg() -> Arg<'static, 'static> {
+    Arg::with_name("dev-mode")
+        .short("d")
+        .long("dev")
+        .takes_value(false)
+        .help("Prevents work directory clean up and prints its path.")
+}
+
+fn readable_names_arg() -> Arg<'static, 'static> {
+    Arg::with_name("readable-names")
+        .long("readable-names")
+        .takes_value(false)
+        .help("Names generated C++ temporaries after what they hold instead of numbering them.")
+}
+
+fn debug_arg() -> Arg<'static, 'static> {
+    Arg::with_name("debug")
+        .short("g")
+        .long("debug")
+        .takes_value(false)
+        .help("Builds with debug info pointing at the .som source, for gdb. Implies --readable-names.")
+}
+
+fn unchecked_arg() -> Arg<'static, 'static> {
+    Arg::with_name("unchecked")
+        .long("unchecked")
+        .takes_value(false)
+        .help("Leaves out the overflow, division by zero and shift checks of whole number arithmetic.")
+}
+
+fn work_dir_arg() -> Arg<'static, 'static> {
+    Arg::with_name("work-dir")
+        .long("work-dir")
+        .takes_value(true)
+        .help("Directory for generated sources instead of a fresh temporary one, never cleaned up.")
+}
 
-        lexer.lex();
+fn emit_arg(default: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("emit")
+        .long("emit")
+        .takes_value(true)
+        .possible_values(&["tokens", "ast", "cpp", "exe"])
+        .default_value(default)
+        .help("Compiler stage to output.")
+}
+
+fn output_arg() -> Arg<'static, 'static> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .takes_value(true)
+        .help("Output path. Stages other than exe are printed to stdout when omitted, exe defaults to som.out.")
+}
 
-        if lexer.tree().len() == 0 {
-            std::process::exit(1);
+fn toolchain_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("cc")
+            .long("cc")
+            .takes_value(true)
+            .help("C++ compiler to build with, defaults to $CXX or g++."),
+        Arg::with_name("opt-level")
+            .short("O")
+            .takes_value(true)
+            .possible_values(&["0", "1", "2", "3"])
+            .default_value("0")
+            .help("Optimisation level passed to the C++ compiler."),
+        Arg::with_name("cxxflag")
+            .long("cxxflag")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .allow_hyphen_values(true)
+            .help("Extra flag passed to the C++ compiler, may be repeated."),
+        Arg::with_name("dynamic")
+            .long("dynamic")
+            .takes_value(false)
+            .help("Links the executable dynamically instead of statically."),
+        Arg::with_name("target-triple")
+            .long("target-triple")
+            .takes_value(true)
+            .help("Target triple for cross compiling."),
+    ]
+}
+
+fn toolchain(args: &ArgMatches) -> utils::Toolchain {
+    let mut toolchain = utils::Toolchain::from_env();
+    if let Some(cc) = args.value_of("cc") {
+        toolchain.cc = cc.to_string();
+    }
+    toolchain.opt_level = args.value_of("opt-level").unwrap().to_string();
+    if let Some(flags) = args.values_of("cxxflag") {
+        toolchain.cxxflags = flags.map(String::from).collect();
+    }
+    toolchain.static_link = args.index_of("dynamic").is_none();
+    toolchain.target_triple = args.value_of("target-triple").map(String::from);
+    toolchain.debug_info = args.index_of("debug").is_some();
+    toolchain
+}
+
+fn read_source(args: &ArgMatches) -> String {
+    read_file(args.value_of("file_name").unwrap())
+}
+fn read_file(file_name: &str) -> String {
+    match fs::read_to_string(file_name) {
+        Ok(content) => content,
+        Err(_) => {
+            eprintln!("Could not read file {}", file_name);
+            std::process::exit(EXIT_IO);
         }
-        let mut global_scope: HashMap<String, compiler::parse::ParseTok> = HashMap::new();
-
-        let mut parser = parse::Parser::new(lexer.tree(), file_content, global_scope);
-
-        parser.init();
-        let mut main_buffer: Vec<String> = vec![String::from("int main() {")];
-        let def = &mut IndexMap::new();
-        for tok in parser.clone().tree() {
-            let gen = compiler::generation::gen(
-                compiler::generation::DescriptorToken {
-                    token_real_type: None,
-                    token: tok,
-                },
-                "_".to_string(),
-                def,
-            );
-            main_buffer.push(gen);
+    }
+}
+
+fn lex(file_content: &str) -> Vec<LexToken> {
+    if file_content.is_empty() {
+        std::process::exit(EXIT_COMPILE);
+    }
+    let mut lexer = Lexer::new(file_content.chars().collect());
+
+    lexer.lex();
+
+    if lexer.tree().is_empty() {
+        std::process::exit(EXIT_COMPILE);
+    }
+    lexer.tree()
+}
+
+fn parse(file_content: String) -> parse::Parser {
+    let tokens = lex(&file_content);
+    let global_scope: HashMap<String, compiler::parse::ParseTok> = HashMap::new();
+
+    let mut parser = parse::Parser::new(tokens, file_content, global_scope);
+
+    parser.init();
+    parser
+}
+
+// In test mode main runs the test blocks instead of the program. Top level
+// definitions are kept so tests can use them but top level calls are skipped.
+fn generate(args: &ArgMatches, parser: parse::Parser, tests: bool) -> Generated {
+    let debug = args.index_of("debug").is_some();
+    generation::reset_ids(debug || args.index_of("readable-names").is_some());
+    generation::set_checks(args.index_of("unchecked").is_none());
+    // Optimised builds leave out the shadow call stack.
+    generation::set_backtraces(args.value_of("opt-level") == Some("0"));
+    generation::set_line_directives(match debug {
+        true => args.value_of("file_name").map(String::from),
+        false => None,
+    });
+    let mut main_buffer: Vec<String> = vec![String::from("int main(int argc, char** argv) {")];
+    let def = &mut IndexMap::new();
+    if tests {
+        generation::init_fn_test(def);
+        main_buffer.push(String::from("std::vector<SOM_TEST> som_tests;"));
+    }
+    let tree = parser.tree();
+    for tok in tree.clone() {
+        let skip = if tests {
+            tok.tok_type == parse::ParseType::FNCALL
+        } else {
+            tok.tok_type == parse::ParseType::TEST
+        };
+        if skip {
+            continue;
         }
+        main_buffer.push(generation::make_statement(tok, def));
+    }
+    let entry = tree.iter().find(|tok| {
+        tok.tok_type == parse::ParseType::FNMAKE && tok.fnmake.clone().unwrap().name == "main"
+    });
+    if tests {
+        main_buffer.push(String::from("return som_run_tests(som_tests);\n}"));
+    } else if let Some(main) = entry {
+        main_buffer.push(generation::make_entry(main) + "\n}");
+    } else {
         main_buffer.push(String::from("return 0;\n}"));
-        let mut defs: Vec<String> = def
-            .values()
-            .map(|item| -> String {
-                if item.ext == false {
-                    return item.def.clone();
-                };
-                String::from("")
-            })
-            .collect();
-
-        let mut extern_defs: Vec<String> = def
-            .values()
-            .map(|item| -> String {
-                if item.ext == true {
-                    return item.def.clone();
-                };
-                String::from("")
-            })
-            .collect();
-
-        defs.append(&mut main_buffer);
-
-        extern_defs.insert(0, "#include<memory>".to_string());
-        extern_defs.insert(0, "#include<vector>".to_string());
-        defs.insert(0, "#include \"som_std.cc\"".to_string());
-
-        let joined = defs.join("\n");
-        if file.index_of("gen").is_none() {
-            utils::make_lib(String::from("som_std"), extern_defs.join("\n"));
-            utils::make_work(joined, true);
-            if file.index_of("dev-mode").is_none() {
-                utils::clean_work();
+    }
+    main_buffer.splice(1..1, generation::main_setup(def));
+    let mut defs: Vec<String> = def
+        .values()
+        .map(|item| -> String {
+            if !item.ext {
+                return item.def.clone();
+            };
+            String::from("")
+        })
+        .collect();
+
+    let mut extern_defs: Vec<String> = def
+        .values()
+        .map(|item| -> String {
+            if item.ext {
+                return item.def.clone();
+            };
+            String::from("")
+        })
+        .collect();
+
+    defs.append(&mut main_buffer);
+
+    extern_defs.insert(0, "#include<memory>".to_string());
+    extern_defs.insert(0, "#include<vector>".to_string());
+    defs.insert(0, "#include \"som_std.cc\"".to_string());
+
+    Generated {
+        module: defs.join("\n"),
+        lib: extern_defs.join("\n"),
+    }
+}
+
+fn work_dir(args: &ArgMatches) -> utils::WorkDir {
+    let work = match args.value_of("work-dir") {
+        Some(path) => utils::WorkDir::user(PathBuf::from(path)),
+        None => utils::WorkDir::new(args.index_of("dev-mode").is_some()),
+    };
+    if args.index_of("dev-mode").is_some() {
+        eprintln!("Work directory: {}", work.path().display());
+    }
+    work
+}
+
+// Writes the generated sources into the work directory and builds them, reporting
+// C++ compiler errors. Returns instead of exiting so the work directory is dropped.
+fn compile(args: &ArgMatches, work: &utils::WorkDir, gen: Generated, output: &Path) -> bool {
+    work.make_lib(String::from("som_std"), gen.lib);
+    work.make_work(generation::resolve_lines(&gen.module, "module.cc"));
+    match work.compile_gen(output, &toolchain(args)) {
+        Ok(()) => true,
+        Err(stderr) => {
+            diagnostic::error(
+                "C++ build failed",
+                1,
+                1,
+                format!(
+                    "the generated code for {} did not compile.\n{}",
+                    args.value_of("file_name").unwrap(),
+                    stderr
+                ),
+            );
+            false
+        }
+    }
+}
+
+fn write_output(content: String, output: Option<&str>) {
+    match output {
+        Some(path) => {
+            if fs::write(path, content).is_err() {
+                eprintln!("Could not write file {}", path);
+                std::process::exit(EXIT_IO);
             }
+        }
+        None => print!("{}", content),
+    }
+}
+
+// Formats files in place, or with --check lists the ones that are not
+// formatted and fails.
+fn fmt(args: &ArgMatches) -> i32 {
+    let mut status = 0;
+    for file_name in args.values_of("files").unwrap() {
+        let source = read_file(file_name);
+        let formatted = format::format(&source);
+        if args.index_of("stdout").is_some() {
+            print!("{}", formatted);
+        } else if formatted == source {
+            continue;
+        } else if args.index_of("check").is_some() {
+            println!("{} is not formatted", file_name);
+            status = 1;
         } else {
-            utils::make_lib(String::from("som_std"), extern_defs.join("\n"));
-            utils::make_work(joined, false);
+            write_output(formatted, Some(file_name));
+        }
+    }
+    status
+}
+
+fn emit(args: &ArgMatches) {
+    let output = args.value_of("output");
+    match args.value_of("emit").unwrap() {
+        "tokens" => {
+            let tokens: Vec<String> = lex(&read_source(args))
+                .iter()
+                .map(|tok| tok.to_string() + "\n")
+                .collect();
+            write_output(tokens.concat(), output);
         }
-        if file.index_of("run").is_some() {
-            let args = file.value_of("run").unwrap();
-            utils::run_gen(args.to_string().split(" ").clone().collect());
+        "ast" => {
+            let parser = parse(read_source(args));
+            write_output(pretty::print_tree(&parser.tree()), output);
+        }
+        "cpp" => {
+            let gen = generate(args, parse(read_source(args)), false);
+            let name = output.unwrap_or("module.cc");
+            write_output(
+                generation::resolve_lines(&gen.translation_unit(), name) + "\n",
+                output,
+            );
+        }
+        _ => build(args, &PathBuf::from(output.unwrap_or("som.out"))),
+    }
+}
+
+fn build(args: &ArgMatches, output: &Path) {
+    let gen = generate(args, parse(read_source(args)), false);
+    let built = compile(args, &work_dir(args), gen, output);
+    if !built {
+        std::process::exit(EXIT_BUILD);
+    }
+}
+
+fn run(args: &ArgMatches, tests: bool) -> i32 {
+    let gen = generate(args, parse(read_source(args)), tests);
+    let work = work_dir(args);
+    let exe = work.path().join("som.out");
+    if !compile(args, &work, gen, &exe) {
+        return EXIT_BUILD;
+    }
+
+    let program_args: Vec<String> = args
+        .values_of("args")
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default();
+    utils::run_gen(&exe, &program_args)
+}
+
+fn main() {
+    let app = App::new("Something")
+        .author("Ashtyn")
+        .version(clap::crate_version!())
+        .about("A functional programming language.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Lexes, parses and type checks a file without generating code.")
+                .arg(file_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Compiles a file to an executable.")
+                .arg(file_arg())
+                .arg(dev_arg())
+                .arg(work_dir_arg())
+                .arg(readable_names_arg())
+                .arg(unchecked_arg())
+                .arg(debug_arg())
+                .arg(output_arg())
+                .arg(emit_arg("exe"))
+                .args(&toolchain_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about(
+                    "Compiles a file and runs the executable, passing along everything after --.",
+                )
+                .arg(file_arg())
+                .arg(dev_arg())
+                .arg(work_dir_arg())
+                .arg(readable_names_arg())
+                .arg(unchecked_arg())
+                .arg(debug_arg())
+                .args(&toolchain_args())
+                .arg(
+                    Arg::with_name("args")
+                        .multiple(true)
+                        .last(true)
+                        .help("Arguments given to the program."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("emit")
+                .about("Outputs a compiler stage, the generated C++ by default.")
+                .arg(file_arg())
+                .arg(dev_arg())
+                .arg(work_dir_arg())
+                .arg(readable_names_arg())
+                .arg(unchecked_arg())
+                .arg(debug_arg())
+                .arg(output_arg())
+                .arg(emit_arg("cpp"))
+                .args(&toolchain_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Compiles the test blocks of a file into a test binary and runs them.")
+                .arg(file_arg())
+                .arg(dev_arg())
+                .arg(work_dir_arg())
+                .arg(readable_names_arg())
+                .arg(unchecked_arg())
+                .arg(debug_arg())
+                .args(&toolchain_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Formats files in place.")
+                .arg(
+                    Arg::with_name("files")
+                        .required(true)
+                        .multiple(true)
+                        .help("input file filenames"),
+                )
+                .arg(Arg::with_name("check").long("check").help(
+                    "Lists files that are not formatted and exits with 1 instead of writing them.",
+                ))
+                .arg(
+                    Arg::with_name("stdout")
+                        .long("stdout")
+                        .conflicts_with("check")
+                        .help("Prints the formatted source instead of writing it."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lsp").about("Starts a language server on stdin and stdout."),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("Removes work directories kept by --dev or left behind by crashes."),
+        );
+
+    match app.get_matches().subcommand() {
+        ("check", Some(args)) => {
+            parse(read_source(args));
         }
+        ("build", Some(args)) | ("emit", Some(args)) => emit(args),
+        ("run", Some(args)) => std::process::exit(run(args, false)),
+        ("test", Some(args)) => std::process::exit(run(args, true)),
+        ("fmt", Some(args)) => std::process::exit(fmt(args)),
+        ("lsp", Some(_)) => std::process::exit(lsp::serve()),
+        ("clean", Some(_)) => utils::clean_work(),
+        _ => unreachable!(),
     }
 }
@@ -0,0 +1,238 @@
+// Golden-file tests over examples/ and tests/corpus/.
+//
+// Every .som file is compiled through `something emit` and its tokens, AST and C++
+// are compared against tests/expected/<dir>/<name>.<stage>.expected. When g++ is
+// installed the program is also run and its stdout compared against
+// <name>.stdout.expected, and files with test blocks have the report of
+// `something test` compared against <name>.test.expected. Source annotations:
+//
+//   // expect-error: <message>   compilation must fail with <message> in its output
+//   // no-run: <reason>          compare compiler stages but never run the program
+//   // args: <a b ..>            command-line arguments given to the program
+//   // exit: <status>            status the program must exit with, 0 by default
+//   // stderr: <text>            stderr of the program must contain <text>
+//   // flags: <flags>            extra flags for emit and run, like --debug
+//
+// `something fmt` output is compared against <name>.fmt.expected and must be
+// left unchanged when formatted again.
+//
+// Every file must also lex losslessly: tokens and trivia put back together give
+// the source, and each token's byte range holds its content.
+//
+// Run with BLESS=1 to write the current output as the new snapshots.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const SOURCE_DIRS: [&str; 2] = ["examples", "tests/corpus"];
+const STAGES: [&str; 3] = ["tokens", "ast", "cpp"];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn something(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_something"))
+        .current_dir(root())
+        .args(args)
+        .output()
+        .expect("Failed to run something")
+}
+
+fn has_cxx() -> bool {
+    Command::new("g++")
+        .arg("--version")
+        .output()
+        .map(|out| out.status.success())
+        .unwrap_or(false)
+}
+
+fn annotation(source: &str, name: &str) -> Option<String> {
+    let prefix = format!("// {}:", name);
+    source
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(&prefix))
+        .map(|rest| rest.trim().to_string())
+}
+
+fn sources() -> Vec<PathBuf> {
+    let mut files = vec![];
+    for dir in SOURCE_DIRS {
+        for entry in fs::read_dir(root().join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "som") {
+                files.push(path.strip_prefix(root()).unwrap().to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+// Compares against the snapshot, or rewrites it in bless mode.
+fn check_snapshot(expected: &Path, actual: &str, failures: &mut Vec<String>) {
+    if env::var("BLESS").is_ok() {
+        fs::create_dir_all(expected.parent().unwrap()).unwrap();
+        fs::write(expected, actual).unwrap();
+        return;
+    }
+    match fs::read_to_string(expected) {
+        Ok(content) if content == actual => {}
+        Ok(content) => failures.push(format!(
+            "{} differs\n--- expected\n{}\n--- actual\n{}",
+            expected.display(),
+            content,
+            actual
+        )),
+        Err(_) => failures.push(format!(
+            "{} is missing, run with BLESS=1 to create it",
+            expected.display()
+        )),
+    }
+}
+
+fn check_lossless(file: &Path, source: &str, failures: &mut Vec<String>) {
+    let mut lexer = compiler::Lexer::new(source.chars().collect());
+    lexer.lex();
+    let tokens = lexer.tree();
+    if compiler::untokenize(&tokens, &lexer.trailing_trivia()) != source {
+        failures.push(format!("{} does not lex losslessly", file.display()));
+    }
+    for tok in &tokens {
+        let loc = tok.loc();
+        if &source[loc.byte_start..loc.byte_end] != tok.content() {
+            failures.push(format!("{} has a wrong span for {}", file.display(), tok));
+        }
+    }
+}
+
+fn check_file(file: &Path, run: bool, failures: &mut Vec<String>) {
+    let source = fs::read_to_string(root().join(file)).unwrap();
+    let file_arg = file.to_str().unwrap();
+
+    if let Some(message) = annotation(&source, "expect-error") {
+        let out = something(&["check", file_arg]);
+        let output = String::from_utf8_lossy(&out.stdout).to_string()
+            + &String::from_utf8_lossy(&out.stderr);
+        if out.status.success() {
+            failures.push(format!("{} compiled but expected an error", file.display()));
+        } else if !output.contains(&message) {
+            failures.push(format!(
+                "{} failed without the expected error {:?}:\n{}",
+                file.display(),
+                message,
+                output
+            ));
+        }
+        return;
+    }
+    check_lossless(file, &source, failures);
+
+    let expected_dir = root()
+        .join("tests/expected")
+        .join(file.parent().unwrap().file_name().unwrap());
+    let name = file.file_stem().unwrap().to_str().unwrap();
+    let flags = annotation(&source, "flags").unwrap_or_default();
+    for stage in STAGES {
+        let emit = format!("--emit={}", stage);
+        let mut command = vec!["emit", file_arg, &emit];
+        command.extend(flags.split_whitespace());
+        let out = something(&command);
+        if !out.status.success() {
+            failures.push(format!(
+                "{} failed at --emit={}:\n{}{}",
+                file.display(),
+                stage,
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            ));
+            return;
+        }
+        check_snapshot(
+            &expected_dir.join(format!("{}.{}.expected", name, stage)),
+            &String::from_utf8_lossy(&out.stdout),
+            failures,
+        );
+    }
+
+    let out = something(&["fmt", "--stdout", file_arg]);
+    let formatted = String::from_utf8_lossy(&out.stdout).to_string();
+    check_snapshot(
+        &expected_dir.join(format!("{}.fmt.expected", name)),
+        &formatted,
+        failures,
+    );
+    let again = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.som", name));
+    fs::write(&again, &formatted).unwrap();
+    if !something(&["fmt", "--check", again.to_str().unwrap()])
+        .status
+        .success()
+    {
+        failures.push(format!(
+            "{} formats differently a second time",
+            file.display()
+        ));
+    }
+
+    if run && annotation(&source, "no-run").is_none() {
+        let args = annotation(&source, "args").unwrap_or_default();
+        let mut command = vec!["run", file_arg];
+        command.extend(flags.split_whitespace());
+        command.push("--");
+        command.extend(args.split_whitespace());
+        let out = something(&command);
+        let status = annotation(&source, "exit").unwrap_or_else(|| String::from("0"));
+        if out.status.code().map(|code| code.to_string()) != Some(status.clone()) {
+            failures.push(format!(
+                "{} did not exit with status {}:\n{}",
+                file.display(),
+                status,
+                String::from_utf8_lossy(&out.stderr)
+            ));
+            return;
+        }
+        if let Some(text) = annotation(&source, "stderr") {
+            if !String::from_utf8_lossy(&out.stderr).contains(&text) {
+                failures.push(format!(
+                    "{} did not print {:?} to stderr:\n{}",
+                    file.display(),
+                    text,
+                    String::from_utf8_lossy(&out.stderr)
+                ));
+            }
+        }
+        check_snapshot(
+            &expected_dir.join(format!("{}.stdout.expected", name)),
+            &String::from_utf8_lossy(&out.stdout),
+            failures,
+        );
+    }
+
+    // Failing tests are part of the snapshot, so the exit status is not checked.
+    if run && source.lines().any(|line| line.starts_with("test \"")) {
+        let out = something(&["test", file_arg]);
+        check_snapshot(
+            &expected_dir.join(format!("{}.test.expected", name)),
+            &String::from_utf8_lossy(&out.stdout),
+            failures,
+        );
+    }
+}
+
+#[test]
+fn golden() {
+    let run = has_cxx();
+    if !run {
+        eprintln!("g++ not found, skipping program output comparisons");
+    }
+
+    let mut failures = vec![];
+    for file in sources() {
+        check_file(&file, run, &mut failures);
+    }
+    if !failures.is_empty() {
+        panic!("{}", failures.join("\n\n"));
+    }
+}
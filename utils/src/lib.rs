@@ -1,76 +1,185 @@
 use nanoid::nanoid;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
 
 use std::env;
 use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
 
-pub fn make_work(content: String) {
+// Root of every work directory, emptied by `clean_work`.
+pub fn work_root() -> PathBuf {
     let mut dir = env::temp_dir();
     dir.push("something_work");
-    if dir.is_dir() == false {
-        fs::create_dir(&dir).expect("Failed to create work directory");
+    dir
+}
+
+// Directory holding the generated sources of a single compilation. Unless kept for
+// debugging it is removed when dropped, so concurrent compilations never share files.
+pub struct WorkDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl WorkDir {
+    pub fn new(keep: bool) -> Self {
+        let mut path = work_root();
+        path.push(format!("{}-{}", process::id(), nanoid!(8)));
+        fs::create_dir_all(&path).expect("Failed to create work directory");
+        Self { path, keep }
+    }
+
+    // User supplied directories may hold other files so they are never removed.
+    pub fn user(path: PathBuf) -> Self {
+        fs::create_dir_all(&path).expect("Failed to create work directory");
+        Self { path, keep: true }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    dir.push("module.cc");
-    fs::write(&dir, content).expect("Failed to write module");
-    // g++ -Wall -o main main.cpp -static
+    pub fn make_work(&self, content: String) {
+        fs::write(self.path.join("module.cc"), content).expect("Failed to write module");
+        // g++ -Wall -o main main.cpp -static
+    }
+
+    pub fn make_lib(&self, name: String, content: String) {
+        fs::write(self.path.join(name + ".cc"), content).expect("Failed to make library.");
+    }
+
+    pub fn compile_gen(&self, output: &Path, toolchain: &Toolchain) -> Result<(), String> {
+        let module = self.path.join("module.cc");
+        let mut cmd = toolchain.command();
+        cmd.arg("-o")
+            .arg(output)
+            .arg(&module)
+            .arg(format!("-O{}", toolchain.opt_level));
+        if toolchain.static_link {
+            cmd.arg("-static");
+        }
+        if toolchain.debug_info {
+            cmd.arg("-g");
+        }
+        cmd.args(&toolchain.cxxflags);
+
+        let build = match cmd.output() {
+            Ok(build) => build,
+            Err(err) => return Err(format!("Could not run {:?}: {}", cmd.get_program(), err)),
+        };
+        if build.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&build.stderr).to_string())
+        }
+    }
 }
 
-pub fn compile_gen() {
-    let mut dir = env::temp_dir();
-    dir.push("something_work");
-    dir.push("module.cc");
-    let mut current_dir = env::current_dir().unwrap();
-    current_dir.push("som.out");
-    let mut build = Command::new("g++")
-        .args([
-            "-o",
-            current_dir.to_str().unwrap(),
-            dir.to_str().unwrap(),
-            "-static",
-        ])
-        .spawn()
-        .expect("Failed to build");
-    build.wait().expect("Failed to build");
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if !self.keep && self.path.is_dir() {
+            fs::remove_dir_all(&self.path).ok();
+        }
+    }
 }
 
-pub fn clean_work() {
-    let mut dir = env::temp_dir();
-    dir.push("something_work");
-    if dir.is_dir() == true {
-        fs::remove_dir_all(dir).expect("Failed to clean up work directory");
+pub struct Toolchain {
+    pub cc: String,
+    pub opt_level: String,
+    pub cxxflags: Vec<String>,
+    pub static_link: bool,
+    pub target_triple: Option<String>,
+    pub debug_info: bool,
+}
+
+impl Toolchain {
+    // g++ unless overridden by the CXX environment variable.
+    pub fn from_env() -> Self {
+        Self {
+            cc: env::var("CXX").unwrap_or_else(|_| String::from("g++")),
+            opt_level: String::from("0"),
+            cxxflags: vec![],
+            static_link: true,
+            target_triple: None,
+            debug_info: false,
+        }
+    }
+
+    // clang takes the triple as a flag while gcc cross compilers are separate
+    // binaries prefixed with it. Only a bare command like g++ can be prefixed,
+    // a compiler given by path is run as it is with the flag.
+    fn command(&self) -> Command {
+        let bare = !self.cc.contains(std::path::is_separator);
+        match &self.target_triple {
+            Some(triple) if self.cc.contains("clang") || !bare => {
+                let mut cmd = Command::new(&self.cc);
+                cmd.arg(format!("--target={}", triple));
+                cmd
+            }
+            Some(triple) => Command::new(format!("{}-{}", triple, self.cc)),
+            None => Command::new(&self.cc),
+        }
     }
 }
 
-pub fn run_gen(args: Vec<&str>) {
-    let child = Command::new("./som.out")
-        .args(args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn();
-
-    let output = child.unwrap().wait_with_output().unwrap();
-
-    if output.status.success() {
-        let raw_output = String::from_utf8(output.stdout);
-        println!("{}", raw_output.unwrap());
-    } else {
-        let out = String::from_utf8(output.stdout);
-        println!("{}", out.unwrap());
-
-        let err = String::from_utf8(output.stderr);
-        println!("{}", err.unwrap());
-        std::process::exit(1);
+// Removes the work directories left behind by kept or killed compilations.
+// Those of compilations still running in other processes are left alone.
+pub fn clean_work() {
+    let root = work_root();
+    let entries = match fs::read_dir(&root) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if in_use(&path) {
+            continue;
+        }
+        // A compilation that just finished may have removed it already.
+        match fs::remove_dir_all(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                eprintln!(
+                    "Could not remove work directory {}: {}",
+                    path.display(),
+                    err
+                )
+            }
+            _ => {}
+        }
     }
+    // Fails while other compilations still have directories in it.
+    let _ = fs::remove_dir(&root);
 }
 
-pub fn make_lib(name: String, content: String) {
-    let mut dir = env::temp_dir();
-    dir.push("something_work");
-    if dir.is_dir() == false {
-        fs::create_dir(&dir).expect("Failed to create work directory");
+// Whether the process that made a work directory, named <pid>-<id>, is still
+// running. Without /proc to ask, directories younger than a day count as in
+// use.
+fn in_use(dir: &Path) -> bool {
+    let pid = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('-').next())
+        .and_then(|pid| pid.parse::<u32>().ok());
+    let pid = match pid {
+        Some(pid) => pid,
+        None => return false,
+    };
+    if Path::new("/proc/self").exists() {
+        return Path::new("/proc").join(pid.to_string()).exists();
     }
-    dir.push(name.clone() + ".cc");
-    fs::write(&dir, content).expect("Failed to make library.");
+    fs::metadata(dir)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < Duration::from_secs(24 * 60 * 60))
+}
+
+pub fn run_gen(exe: &Path, args: &[String]) -> i32 {
+    let status = Command::new(exe)
+        .args(args)
+        .status()
+        .expect("Failed to run executable");
+
+    // A program killed by a signal has no exit code, report it as a failure.
+    status.code().unwrap_or(1)
 }